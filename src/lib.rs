@@ -0,0 +1,9 @@
+mod petroglyph;
+
+pub use petroglyph::MegaFile;
+pub use petroglyph::mega_file::{ContentHash, CreateInput, CreateOptions, DataOrder, EncryptionKey, EntryChange,
+                                EntryFilter, EntryReader, EntrySource, ExportFile, ExtractOptions, FileMeta,
+                                Filename, Finding, Header, Manifest, ManifestFile, MegError, MegVersion,
+                                MergeSource, Override, PackEntry, PathPolicy, TableRecord, UpdateOptions, Vfs,
+                                VfsSource};
+pub use petroglyph::mega_file::crc;
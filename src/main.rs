@@ -1,7 +1,80 @@
-mod petroglyph;
-
+use serde::Serialize;
 use structopt::StructOpt;
-use std::path::PathBuf;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use meg_file_creator::{CreateInput, CreateOptions, EncryptionKey, EntryChange, ExtractOptions, FileMeta, Header,
+                       MegaFile, MegError, MegVersion, MergeSource, PathPolicy, UpdateOptions, Vfs};
+
+#[derive(Debug, StructOpt)]
+struct KeyOpt
+{
+    #[structopt(long = "key-file", parse(from_os_str))]
+    key_file: Option<PathBuf>,
+    #[structopt(long = "key", requires = "iv")]
+    key: Option<String>,
+    #[structopt(long = "iv", requires = "key")]
+    iv: Option<String>
+}
+
+impl KeyOpt
+{
+    fn load(&self) -> Result<Option<EncryptionKey>, MegError> {
+        match (&self.key_file, &self.key, &self.iv) {
+            (Some(key_file), _, _) => EncryptionKey::from_file(key_file).map(Some),
+            (None, Some(key), Some(iv)) => EncryptionKey::from_hex(key, iv).map(Some),
+            _ => Ok(None)
+        }
+    }
+}
+
+#[derive(Debug)]
+enum ExportFormat
+{
+    Tar,
+}
+
+impl std::str::FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<ExportFormat, String> {
+        match s.to_ascii_lowercase().as_str() {
+            "tar" => Ok(ExportFormat::Tar),
+            _ => Err(format!("Unknown export format '{}'. Expected tar", s))
+        }
+    }
+}
+
+#[derive(Debug)]
+enum OutputFormat
+{
+    Table,
+    Json,
+    Csv,
+}
+
+impl std::str::FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<OutputFormat, String> {
+        match s.to_ascii_lowercase().as_str() {
+            "table" => Ok(OutputFormat::Table),
+            "json" => Ok(OutputFormat::Json),
+            "csv" => Ok(OutputFormat::Csv),
+            _ => Err(format!("Unknown output format '{}'. Expected one of table, json, csv", s))
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ArchiveInfo<'a>
+{
+    version: MegVersion,
+    header: &'a Header,
+    data_start: u64,
+    files: Vec<FileMeta>,
+}
 
 #[derive(Debug, StructOpt)]
 #[structopt(name = "example", about = "An example of StructOpt usage.")]
@@ -11,49 +84,437 @@ enum ArgsOpt
         #[structopt(parse(from_os_str))]
         input_file: PathBuf,
         #[structopt(parse(from_os_str))]
-        output_dir: Option<PathBuf>
+        output_dir: Option<PathBuf>,
+        #[structopt(long = "pattern", short = "p")]
+        patterns: Vec<String>,
+        #[structopt(long = "unsafe-paths", default_value = "reject")]
+        path_policy: PathPolicy,
+        #[structopt(long = "meg-version")]
+        version: Option<MegVersion>,
+        #[structopt(flatten)]
+        key: KeyOpt
+    },
+    Export {
+        #[structopt(parse(from_os_str))]
+        input_file: PathBuf,
+        #[structopt(parse(from_os_str))]
+        output_file: Option<PathBuf>,
+        #[structopt(long = "format", default_value = "tar")]
+        format: ExportFormat,
+        #[structopt(long = "pattern", short = "p")]
+        patterns: Vec<String>,
+        #[structopt(long = "unsafe-paths", default_value = "reject")]
+        path_policy: PathPolicy,
+        #[structopt(long = "meg-version")]
+        version: Option<MegVersion>,
+        #[structopt(flatten)]
+        key: KeyOpt
     },
     Paths {
         #[structopt(parse(from_os_str))]
-        input: PathBuf
+        input: PathBuf,
+        #[structopt(long = "meg-version")]
+        version: Option<MegVersion>,
+        #[structopt(long = "format", default_value = "table")]
+        format: OutputFormat,
+        #[structopt(flatten)]
+        key: KeyOpt
     },
     Info {
         #[structopt(parse(from_os_str))]
-        input: PathBuf
+        input: PathBuf,
+        #[structopt(long = "meg-version")]
+        version: Option<MegVersion>,
+        #[structopt(long = "format", default_value = "table")]
+        format: OutputFormat,
+        #[structopt(flatten)]
+        key: KeyOpt
     },
     Create {
         #[structopt(parse(from_os_str))]
         input_directory: PathBuf,
         #[structopt(parse(from_os_str))]
-        output_file: Option<PathBuf>
+        output_file: Option<PathBuf>,
+        #[structopt(long = "meg-version")]
+        version: Option<MegVersion>,
+        #[structopt(long = "mount-prefix", default_value = "")]
+        mount_prefix: String,
+        #[structopt(long = "check-reproducible")]
+        check_reproducible: bool,
+        #[structopt(long = "deduplicate")]
+        deduplicate: bool,
+        #[structopt(long = "alignment", default_value = "1")]
+        alignment: u32,
+        #[structopt(long = "reserve-table-space", default_value = "0")]
+        reserved_table_space: u32,
+        #[structopt(flatten)]
+        key: KeyOpt
+    },
+    Update {
+        #[structopt(parse(from_os_str))]
+        input_file: PathBuf,
+        #[structopt(parse(from_os_str))]
+        output_file: Option<PathBuf>,
+        #[structopt(long = "add", parse(try_from_str = parse_addition))]
+        additions: Vec<(String, PathBuf)>,
+        #[structopt(long = "remove")]
+        removals: Vec<String>,
+        #[structopt(long = "meg-version")]
+        version: Option<MegVersion>,
+        #[structopt(flatten)]
+        key: KeyOpt
+    },
+    Merge {
+        #[structopt(parse(from_os_str))]
+        output_file: PathBuf,
+        #[structopt(parse(from_os_str), required = true)]
+        sources: Vec<PathBuf>,
+        #[structopt(long = "meg-version")]
+        version: Option<MegVersion>,
+        #[structopt(long = "mount-prefix", default_value = "")]
+        mount_prefix: String,
+        #[structopt(long = "encrypt")]
+        encrypt: bool,
+        #[structopt(flatten)]
+        key: KeyOpt
+    },
+    Resolve {
+        #[structopt(parse(from_os_str))]
+        mega_files_xml: PathBuf,
+        path: String,
+        #[structopt(long = "game-dir", parse(from_os_str))]
+        game_directory: Option<PathBuf>,
+        #[structopt(long = "loose-files", parse(from_os_str))]
+        loose_directory: Option<PathBuf>,
+        #[structopt(flatten)]
+        key: KeyOpt
+    },
+    Diff {
+        #[structopt(parse(from_os_str))]
+        old_file: PathBuf,
+        #[structopt(parse(from_os_str))]
+        new_file: PathBuf,
+        #[structopt(long = "machine-readable")]
+        machine_readable: bool,
+        #[structopt(flatten)]
+        key: KeyOpt
+    },
+    Verify {
+        #[structopt(parse(from_os_str))]
+        input: PathBuf,
+        #[structopt(long = "meg-version")]
+        version: Option<MegVersion>,
+        #[structopt(flatten)]
+        key: KeyOpt
+    },
+    Convert {
+        #[structopt(parse(from_os_str))]
+        input_file: PathBuf,
+        #[structopt(parse(from_os_str))]
+        output_file: PathBuf,
+        #[structopt(long = "to")]
+        version: MegVersion,
+        #[structopt(long = "encrypt")]
+        encrypt: bool,
+        #[structopt(flatten)]
+        key: KeyOpt
     }
 }
 
-fn main()
+// Additions are given as INTERNAL\NAME=path/to/file.
+fn parse_addition(argument: &str) -> Result<(String, PathBuf), String> {
+    match argument.split_once('=') {
+        Some((name, path)) if !name.is_empty() && !path.is_empty() => Ok((name.to_string(), PathBuf::from(path))),
+        _ => Err(format!("Expected NAME=PATH, got '{}'", argument))
+    }
+}
+
+// One tab separated line per change: kind, name, old size, new size, old hash, new hash.
+fn format_change_record(change: &EntryChange) -> String {
+    match change {
+        EntryChange::Added { name, size } => format!("added\t{}\t\t{}\t\t", name, size),
+        EntryChange::Removed { name, size } => format!("removed\t{}\t{}\t\t\t", name, size),
+        EntryChange::Modified { name, old_size, new_size, old_hash, new_hash } =>
+            format!("modified\t{}\t{}\t{}\t{}\t{}", name, old_size, new_size, old_hash, new_hash),
+    }
+}
+
+fn to_json<T: Serialize>(value: &T) -> Result<String, MegError> {
+    serde_json::to_string_pretty(value).map_err(|error| MegError::Io(error.into()))
+}
+
+// Fields with separators, quotes or line breaks are quoted, doubling embedded quotes.
+fn csv_field(field: &str) -> String {
+    if field.contains(&[',', '"', '\n', '\r'][..]) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    }
+    else {
+        field.to_string()
+    }
+}
+
+// One archive record with the header fields and the computed data start, then one entry
+// record per file. Each record only fills in its own columns.
+fn print_info_csv(info: &ArchiveInfo) {
+    println!("record,version,encrypted,num_filenames,num_files,data_start,name,crc,index,name_index,size,start,flags");
+    println!("archive,{},{},{},{},{},,,,,,,", info.version, info.header.encrypted, info.header.num_filenames,
+             info.header.num_files, info.data_start);
+    for meta in &info.files {
+        let record = &meta.table_record;
+        println!("entry,,,,,,{},{:#010X},{},{},{},{},{}", csv_field(&meta.internal_file_name.filename),
+                 record.crc, record.index, record.name, record.size, record.start, record.flags);
+    }
+}
+
+fn print_info_table(info: &ArchiveInfo) {
+    println!("{} archive: {}", info.version, info.header);
+    for meta in &info.files {
+        println!("{}", meta);
+    }
+}
+
+fn open_mega_file(input: &Path,
+                  version: Option<MegVersion>,
+                  key: &KeyOpt) -> Result<MegaFile, MegError> {
+    match (version, key.load()?) {
+        (Some(version), key) => MegaFile::create_with_version(input, version, key),
+        (None, Some(key)) => MegaFile::create_with_key(input, key),
+        (None, None) => MegaFile::create(input)
+    }
+}
+
+fn print_written_entries(mega_file: &MegaFile) {
+    for meta in mega_file.get_metadata_iterator() {
+        println!("Wrote {} bytes to file {:?}", meta.table_record.size, meta.internal_file_name.filename);
+    }
+}
+
+fn copy_stdin_to_temporary_file() -> Result<tempfile::NamedTempFile, MegError> {
+    let mut stdin_file = tempfile::NamedTempFile::new()?;
+    std::io::copy(&mut std::io::stdin().lock(), &mut stdin_file)?;
+    Ok(stdin_file)
+}
+
+// Entries are read from the input while the output is written, so an output that is the
+// input goes to a temporary file which then replaces the input.
+fn write_archive<F>(mega_file: MegaFile,
+                    input_file: &Path,
+                    output_file: &Path,
+                    write: F) -> Result<(), MegError>
+    where F: FnOnce(&MegaFile, &Path) -> Result<MegaFile, MegError>
+{
+    if !mega_file.is_stored_at(output_file) {
+        print_written_entries(&write(&mega_file, output_file)?);
+        return Ok(());
+    }
+
+    let temporary_file = input_file.with_extension("meg.tmp");
+    print_written_entries(&write(&mega_file, &temporary_file)?);
+    drop(mega_file);
+    std::fs::rename(&temporary_file, input_file)?;
+    Ok(())
+}
+
+fn run(args: ArgsOpt) -> Result<(), MegError>
 {
-    match ArgsOpt::from_args() {
-        ArgsOpt::Extract {input_file, output_dir} => {
-            let output_dir = output_dir.unwrap_or(PathBuf::from("."));
+    match args {
+        ArgsOpt::Extract {input_file, output_dir, patterns, path_policy, version, key} => {
+            let output_dir = output_dir.unwrap_or_else(|| PathBuf::from("."));
 
-            let mega_file = petroglyph::MegaFile::create(&input_file).unwrap();
-            mega_file.extract_files_to(&output_dir).unwrap();
+            let mega_file = open_mega_file(&input_file, version, &key)?;
+            mega_file.extract_files_with_options(&output_dir, &ExtractOptions { patterns, path_policy })?;
+        },
+        ArgsOpt::Export {input_file, output_file, format, patterns, path_policy, version, key} => {
+            let mega_file = open_mega_file(&input_file, version, &key)?;
+            let options = ExtractOptions { patterns, path_policy };
+            // Without an output file, or with -, the export goes to stdout for piping.
+            let writer: Box<dyn Write> = match output_file {
+                Some(output_file) if output_file != Path::new("-") =>
+                    Box::new(BufWriter::new(File::create(output_file)?)),
+                _ => Box::new(BufWriter::new(std::io::stdout().lock()))
+            };
+            match format {
+                ExportFormat::Tar => mega_file.export_tar(writer, &options)?.flush()?
+            }
+        },
+        ArgsOpt::Paths {input, version, format, key} => {
+            let mega_file = open_mega_file(&input, version, &key)?;
+            let file_names: Vec<&String> = mega_file.get_file_name_iterator().collect();
+            match format {
+                OutputFormat::Json => println!("{}", to_json(&file_names)?),
+                OutputFormat::Csv => {
+                    println!("name");
+                    for file_name in file_names {
+                        println!("{}", csv_field(file_name));
+                    }
+                },
+                OutputFormat::Table => {
+                    for file_name in file_names {
+                        println!("{}", file_name);
+                    }
+                }
+            }
+        },
+        ArgsOpt::Info {input, version, format, key} => {
+            let mega_file = open_mega_file(&input, version, &key)?;
+            let info = ArchiveInfo {
+                version: mega_file.get_version(),
+                header: mega_file.get_header(),
+                data_start: mega_file.get_data_start(),
+                files: mega_file.get_metadata_iterator().collect()
+            };
+            match format {
+                OutputFormat::Json => println!("{}", to_json(&info)?),
+                OutputFormat::Csv => print_info_csv(&info),
+                OutputFormat::Table => print_info_table(&info)
+            }
         },
-        ArgsOpt::Paths {input} => {
-            let mega_file = petroglyph::MegaFile::create(&input).unwrap();
-            for file_name in mega_file.get_file_name_iterator() {
-                println!("{}", file_name);
+        ArgsOpt::Create{ input_directory, output_file, version, mount_prefix, check_reproducible, deduplicate,
+                         alignment, reserved_table_space, key } => {
+            let from_stdin = input_directory == Path::new("-");
+            let output_file = match output_file {
+                Some(output_file) => output_file,
+                None if from_stdin => return Err(MegError::MissingOutputFile),
+                None => input_directory.with_extension("meg")
+            };
+            let encryption = key.load()?;
+            let options = CreateOptions {
+                version: version.unwrap_or(if encryption.is_some() { MegVersion::V3 } else { MegVersion::V1 }),
+                encryption,
+                mount_prefix,
+                deduplicate,
+                alignment,
+                reserved_table_space,
+                ..CreateOptions::default()
+            };
+
+            // Besides directories, tar and zip files are packed member by member, manifests
+            // list the files to pack, and - reads a tar stream from stdin. Tars may be gzipped.
+            // Stdin is copied to a temporary file first, which is read like any tar file and
+            // removed once the archive is written.
+            let stdin_file = if from_stdin { Some(copy_stdin_to_temporary_file()?) } else { None };
+            let input = match &stdin_file {
+                Some(stdin_file) => CreateInput::TarFile(stdin_file.path()),
+                None => CreateInput::detect(&input_directory)?
+            };
+            if check_reproducible {
+                let hash = MegaFile::check_reproducible(input, &options)?;
+                println!("Reproducible build, sha256 {}", hash);
+            }
+            let mega_file = MegaFile::create_from_input(input, &output_file, &options)?;
+            print_written_entries(&mega_file);
+            if deduplicate {
+                println!("Deduplication saved {} bytes", mega_file.get_shared_data_size());
             }
+        },
+        ArgsOpt::Update {input_file, output_file, additions, removals, version, key} => {
+            let mega_file = open_mega_file(&input_file, version, &key)?;
+            let update = UpdateOptions { files: additions, removals };
+            let options = CreateOptions {
+                version: mega_file.get_version(),
+                encryption: key.load()?.filter(|_| mega_file.get_header().encrypted),
+                ..CreateOptions::default()
+            };
 
+            // Without an output file the archive is updated in place.
+            let output_file = output_file.unwrap_or_else(|| input_file.clone());
+            write_archive(mega_file, &input_file, &output_file,
+                          |mega_file, path| mega_file.update_to(path, &update, &options))?;
         },
-        ArgsOpt::Info {input} => {
-            let mega_file = petroglyph::MegaFile::create(&input).unwrap();
-            for mega_file in mega_file.get_metadata_iterator() {
-                println!("{}", mega_file);
+        ArgsOpt::Merge {output_file, sources, version, mount_prefix, encrypt, key} => {
+            // Directories are packed as loose files, anything else is opened as an archive.
+            let archives = sources
+                .iter()
+                .filter(|source| !source.is_dir())
+                .map(|source| open_mega_file(source, None, &key))
+                .collect::<Result<Vec<MegaFile>, MegError>>()?;
+            let mut archive_iterator = archives.iter();
+            let merge_sources: Vec<MergeSource> = sources
+                .iter()
+                .map(|source| {
+                    if source.is_dir() {
+                        MergeSource::Directory(source)
+                    }
+                    else {
+                        MergeSource::Archive(archive_iterator.next().unwrap())
+                    }
+                })
+                .collect();
+
+            let encryption = if encrypt { Some(key.load()?.ok_or(MegError::MissingEncryptionKey)?) } else { None };
+            let options = CreateOptions {
+                version: version.unwrap_or(if encryption.is_some() { MegVersion::V3 } else { MegVersion::V1 }),
+                encryption,
+                mount_prefix,
+                ..CreateOptions::default()
+            };
+            let (mega_file, overrides) = MegaFile::merge(&merge_sources, &output_file, &options)?;
+            print_written_entries(&mega_file);
+            for entry_override in overrides {
+                println!("Override {}: {} replaces {}", entry_override.name,
+                         sources[entry_override.winner].display(), sources[entry_override.overridden].display());
             }
+        },
+        ArgsOpt::Resolve {mega_files_xml, path, game_directory, loose_directory, key} => {
+            // MegaFiles.xml normally lives in the Data directory of the game directory.
+            let game_directory = game_directory.unwrap_or_else(|| {
+                mega_files_xml.parent().and_then(Path::parent).unwrap_or_else(|| Path::new(".")).to_path_buf()
+            });
+            let vfs = Vfs::create(&mega_files_xml, &game_directory, loose_directory, key.load()?)?;
+            let sources = vfs.resolve_all(&path);
+            if sources.is_empty() {
+                return Err(MegError::MissingEntries(vec![path]));
+            }
+            println!("{} resolves to {}", path, sources[0]);
+            for shadowed in &sources[1..] {
+                println!("  shadows {}", shadowed);
+            }
+        },
+        ArgsOpt::Diff {old_file, new_file, machine_readable, key} => {
+            let old = open_mega_file(&old_file, None, &key)?;
+            let new = open_mega_file(&new_file, None, &key)?;
+            for change in old.diff(&new)? {
+                if machine_readable {
+                    println!("{}", format_change_record(&change));
+                }
+                else {
+                    println!("{}", change);
+                }
+            }
+        },
+        ArgsOpt::Verify {input, version, key} => {
+            let findings = MegaFile::verify_archive(&input, version, key.load()?)?;
+            for finding in &findings {
+                println!("{}", finding);
+            }
+            if !findings.is_empty() {
+                return Err(MegError::VerificationFailed { findings: findings.len() });
+            }
+            println!("{}: no problems found", input.display());
+        },
+        ArgsOpt::Convert{ input_file, output_file, version, encrypt, key } => {
+            let encryption = key.load()?;
+            let mega_file = match encryption.clone() {
+                Some(key) => MegaFile::create_with_key(&input_file, key)?,
+                None => MegaFile::create(&input_file)?
+            };
+
+            let options = CreateOptions {
+                version,
+                encryption: if encrypt { Some(encryption.ok_or(MegError::MissingEncryptionKey)?) } else { None },
+                ..CreateOptions::default()
+            };
+            write_archive(mega_file, &input_file, &output_file,
+                          |mega_file, path| mega_file.convert_to(path, &options))?;
         }
-        ArgsOpt::Create{ input_directory, output_file } => {
-            let output_file = output_file.unwrap_or(input_directory.with_extension("meg"));
-            let _mega_file = petroglyph::MegaFile::create_from_directory(&input_directory, &output_file);
-        }
+    }
+    Ok(())
+}
+
+fn main()
+{
+    if let Err(error) = run(ArgsOpt::from_args()) {
+        eprintln!("error: {}", error);
+        std::process::exit(1);
     }
 }
@@ -0,0 +1,73 @@
+use super::{ContentHash, FileMeta, Filename, MegaFile, MegError};
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EntryChange
+{
+    Added { name: String, size: u32 },
+    Removed { name: String, size: u32 },
+    Modified { name: String, old_size: u32, new_size: u32, old_hash: ContentHash, new_hash: ContentHash },
+}
+
+impl EntryChange
+{
+    pub fn get_name(&self) -> &str {
+        match self {
+            EntryChange::Added { name, .. } => name,
+            EntryChange::Removed { name, .. } => name,
+            EntryChange::Modified { name, .. } => name,
+        }
+    }
+}
+
+impl fmt::Display for EntryChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EntryChange::Added { name, size } => write!(f, "+ {} ({} bytes)", name, size),
+            EntryChange::Removed { name, size } => write!(f, "- {} ({} bytes)", name, size),
+            EntryChange::Modified { name, old_size, new_size, .. } =>
+                write!(f, "M {} ({} -> {} bytes)", name, old_size, new_size),
+        }
+    }
+}
+
+// Entries are matched by normalized name. Entries present in both archives are hashed
+// while streaming, since equal sizes say nothing about equal content.
+pub fn diff(old: &MegaFile, new: &MegaFile) -> Result<Vec<EntryChange>, MegError> {
+    let old_entries = entries_by_name(old);
+    let new_entries = entries_by_name(new);
+
+    let mut changes = Vec::new();
+    for (name, old_meta) in &old_entries {
+        match new_entries.get(name) {
+            None => changes.push(EntryChange::Removed { name: name.clone(), size: old_meta.table_record.size }),
+            Some(new_meta) => {
+                let old_hash = old.hash_entry(&old_meta.table_record)?;
+                let new_hash = new.hash_entry(&new_meta.table_record)?;
+                if old_hash != new_hash || old_meta.table_record.size != new_meta.table_record.size {
+                    changes.push(EntryChange::Modified { name: name.clone(),
+                                                         old_size: old_meta.table_record.size,
+                                                         new_size: new_meta.table_record.size,
+                                                         old_hash,
+                                                         new_hash });
+                }
+            }
+        }
+    }
+    for (name, new_meta) in &new_entries {
+        if !old_entries.contains_key(name) {
+            changes.push(EntryChange::Added { name: name.clone(), size: new_meta.table_record.size });
+        }
+    }
+
+    changes.sort_by(|first, second| first.get_name().cmp(second.get_name()));
+    Ok(changes)
+}
+
+fn entries_by_name(mega_file: &MegaFile) -> BTreeMap<String, FileMeta> {
+    mega_file.get_metadata_iterator()
+             .map(|meta| (Filename::normalize(&meta.internal_file_name.filename), meta))
+             .collect()
+}
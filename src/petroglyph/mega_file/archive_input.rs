@@ -0,0 +1,101 @@
+use super::{Filename, MegError, PackEntry};
+
+use flate2::read::MultiGzDecoder;
+
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::sync::Arc;
+
+const GZIP_MAGIC: [u8; 2] = [0x1F, 0x8B];
+
+// Tar and zip member names become internal names the same way paths below an input
+// directory do, so packing a tarball of a directory gives the same archive as the directory.
+fn internal_name(member_path: &Path, mount_prefix: &str) -> Result<String, MegError> {
+    Ok(Filename::from_relative_path(member_path, Path::new(""), mount_prefix)?.filename)
+}
+
+pub fn entries_from_tar_file(path: &Path, mount_prefix: &str) -> Result<Vec<PackEntry>, MegError> {
+    entries_from_tar(File::open(path)?, mount_prefix)
+}
+
+// Streams like stdin cannot be read twice, so they are copied to an anonymous temporary
+// file that is then read like a tar file. This takes disk space rather than memory.
+pub fn entries_from_tar_reader<R: Read>(mut reader: R, mount_prefix: &str) -> Result<Vec<PackEntry>, MegError> {
+    let mut spool = tempfile::tempfile()?;
+    std::io::copy(&mut reader, &mut spool)?;
+    spool.seek(SeekFrom::Start(0))?;
+    entries_from_tar(spool, mount_prefix)
+}
+
+// A tar is read twice: once here for the member headers, and once more when writing,
+// straight from the offsets where tar stores the member contents. Gzip compressed tars have
+// no such offsets, so they are decompressed to an anonymous temporary file first.
+fn entries_from_tar(mut file: File, mount_prefix: &str) -> Result<Vec<PackEntry>, MegError> {
+    if is_gzip(&mut file)? {
+        let mut decompressed = tempfile::tempfile()?;
+        std::io::copy(&mut MultiGzDecoder::new(BufReader::new(file)), &mut decompressed)?;
+        decompressed.seek(SeekFrom::Start(0))?;
+        file = decompressed;
+    }
+
+    let file = Arc::new(file);
+    let mut archive = tar::Archive::new(&*file);
+    let mut pack_entries = Vec::new();
+    for entry in archive.entries_with_seek()? {
+        let entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        pack_entries.push(PackEntry::from_range(internal_name(&entry.path()?, mount_prefix)?,
+                                                Arc::clone(&file),
+                                                entry.raw_file_position(),
+                                                entry.size())?);
+    }
+    Ok(pack_entries)
+}
+
+// Leaves the file at its start whatever it holds.
+fn is_gzip(file: &mut File) -> Result<bool, MegError> {
+    let mut magic = Vec::new();
+    file.by_ref().take(GZIP_MAGIC.len() as u64).read_to_end(&mut magic)?;
+    file.seek(SeekFrom::Start(0))?;
+    Ok(magic == GZIP_MAGIC)
+}
+
+// Stored members are copied from the zip file as is and deflated ones are inflated while
+// they are written, which covers what common zip tools produce.
+pub fn entries_from_zip_file(path: &Path, mount_prefix: &str) -> Result<Vec<PackEntry>, MegError> {
+    let file = Arc::new(File::open(path)?);
+    let mut archive = zip::ZipArchive::new(&*file).map_err(zip_error)?;
+    let mut pack_entries = Vec::new();
+    for i in 0..archive.len() {
+        let member = archive.by_index_raw(i).map_err(zip_error)?;
+        if member.is_dir() {
+            continue;
+        }
+
+        let name = internal_name(Path::new(member.name()), mount_prefix)?;
+        if member.encrypted() {
+            return Err(MegError::UnsupportedZipMember { name: member.name().to_string(),
+                                                        reason: "it is encrypted".to_string() });
+        }
+        let pack_entry = match member.compression() {
+            zip::CompressionMethod::Stored =>
+                PackEntry::from_range(name, Arc::clone(&file), member.data_start(), member.size())?,
+            zip::CompressionMethod::Deflated =>
+                PackEntry::from_deflated(name, Arc::clone(&file), member.data_start(), member.compressed_size(),
+                                         member.size())?,
+            method => return Err(MegError::UnsupportedZipMember {
+                name: member.name().to_string(),
+                reason: format!("it is compressed with {}", method)
+            })
+        };
+        pack_entries.push(pack_entry);
+    }
+    Ok(pack_entries)
+}
+
+fn zip_error(error: zip::result::ZipError) -> MegError {
+    MegError::Io(error.into())
+}
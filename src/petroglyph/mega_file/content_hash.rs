@@ -0,0 +1,62 @@
+use sha2::{Digest, Sha256};
+
+use std::io::{Read, Write};
+
+use super::entry_reader;
+use super::error::MegError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ContentHash([u8; 32]);
+
+impl ContentHash
+{
+    // Hashes the entry while it streams through, so large entries are never held in memory.
+    pub fn from_reader<R: Read>(reader: &mut R, expected_size: u64) -> Result<ContentHash, MegError> {
+        let mut hasher = Sha256::new();
+        entry_reader::copy_entry(reader, &mut hasher, expected_size)?;
+        Ok(ContentHash(hasher.finalize().into()))
+    }
+}
+
+// Hashes everything written to it, so output can be hashed without being stored.
+pub struct HashWriter
+{
+    hasher: Sha256
+}
+
+impl HashWriter
+{
+    pub fn new() -> HashWriter {
+        HashWriter { hasher: Sha256::new() }
+    }
+
+    pub fn finish(self) -> ContentHash {
+        ContentHash(self.hasher.finalize().into())
+    }
+}
+
+impl Default for HashWriter {
+    fn default() -> HashWriter {
+        HashWriter::new()
+    }
+}
+
+impl Write for HashWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.hasher.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for ContentHash {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for byte in &self.0 {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
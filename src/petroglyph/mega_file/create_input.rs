@@ -0,0 +1,43 @@
+use std::path::Path;
+
+use super::MegError;
+
+// Where the entries of a new archive come from. Directories, tar and zip files are packed in
+// name order, manifests in the order they list their files.
+#[derive(Debug, Clone, Copy)]
+pub enum CreateInput<'a>
+{
+    Directory(&'a Path),
+    TarFile(&'a Path),
+    ZipFile(&'a Path),
+    Manifest(&'a Path),
+}
+
+impl<'a> CreateInput<'a>
+{
+    // Picks the input from the extension of an existing file. Tar files may be gzip
+    // compressed, other files cannot be packed, and anything else is a directory.
+    pub fn detect(path: &'a Path) -> Result<CreateInput<'a>, MegError> {
+        if !path.is_file() {
+            return Ok(CreateInput::Directory(path));
+        }
+
+        let file_name = path.file_name()
+                            .and_then(|file_name| file_name.to_str())
+                            .unwrap_or_default()
+                            .to_ascii_lowercase();
+        let has_suffix = |suffixes: &[&str]| suffixes.iter().any(|suffix| file_name.ends_with(suffix));
+        if has_suffix(&[".tar", ".tar.gz", ".tgz"]) {
+            Ok(CreateInput::TarFile(path))
+        }
+        else if has_suffix(&[".zip"]) {
+            Ok(CreateInput::ZipFile(path))
+        }
+        else if has_suffix(&[".toml", ".json"]) {
+            Ok(CreateInput::Manifest(path))
+        }
+        else {
+            Err(MegError::UnsupportedInput(path.to_path_buf()))
+        }
+    }
+}
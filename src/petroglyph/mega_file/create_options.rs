@@ -0,0 +1,45 @@
+use serde::Deserialize;
+
+use super::{EncryptionKey, MegVersion};
+
+// Records are always sorted by CRC. The data of the entries either follows the same order,
+// or the order the entries were given in, for example to keep files loaded together close.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DataOrder
+{
+    #[default]
+    Crc,
+    #[serde(alias = "manifest")]
+    Input,
+}
+
+#[derive(Debug, Clone)]
+pub struct CreateOptions
+{
+    pub version: MegVersion,
+    pub encryption: Option<EncryptionKey>,
+    pub mount_prefix: String,
+    pub data_order: DataOrder,
+    // Entries with identical content point at one copy of the data.
+    pub deduplicate: bool,
+    // The data of each non-empty entry starts at a multiple of this, padded with zeros.
+    pub alignment: u32,
+    // Zero bytes left between the tables and the data, so the tables can grow in place.
+    pub reserved_table_space: u32,
+}
+
+impl Default for CreateOptions
+{
+    fn default() -> CreateOptions {
+        CreateOptions {
+            version: MegVersion::V1,
+            encryption: None,
+            mount_prefix: String::new(),
+            data_order: DataOrder::Crc,
+            deduplicate: false,
+            alignment: 1,
+            reserved_table_space: 0
+        }
+    }
+}
@@ -0,0 +1,268 @@
+use aes::Aes128;
+use cbc::cipher::generic_array::GenericArray;
+use cbc::cipher::{BlockDecryptMut, BlockEncryptMut, KeyIvInit};
+
+use std::io::{Read, Write};
+use std::path::Path;
+
+use super::error::MegError;
+
+pub const BLOCK_SIZE: usize = 16;
+const STREAM_CHUNK_SIZE: usize = 64 * 1024;
+
+type Aes128CbcEncryptor = cbc::Encryptor<Aes128>;
+type Aes128CbcDecryptor = cbc::Decryptor<Aes128>;
+
+// Encrypted v3 archives use AES-128 in CBC mode. The filename table, every table record
+// and every encrypted entry is its own CBC stream starting from the same key and IV,
+// zero padded up to a whole number of blocks.
+#[derive(Clone, PartialEq, Eq)]
+pub struct EncryptionKey
+{
+    key: [u8; BLOCK_SIZE],
+    iv: [u8; BLOCK_SIZE],
+}
+
+impl std::fmt::Debug for EncryptionKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "EncryptionKey {{ .. }}")
+    }
+}
+
+impl EncryptionKey
+{
+    pub fn create(key: [u8; BLOCK_SIZE], iv: [u8; BLOCK_SIZE]) -> EncryptionKey {
+        EncryptionKey{ key, iv }
+    }
+
+    pub fn from_hex(key: &str, iv: &str) -> Result<EncryptionKey, MegError> {
+        Ok(EncryptionKey::create(parse_hex_block(key)?, parse_hex_block(iv)?))
+    }
+
+    // A key file either holds the 16 key bytes followed by the 16 IV bytes, or the same
+    // 32 bytes written as hexadecimal text.
+    pub fn from_file(path: &Path) -> Result<EncryptionKey, MegError> {
+        let content = std::fs::read(path)?;
+        if content.len() == BLOCK_SIZE * 2 {
+            let mut key = [0; BLOCK_SIZE];
+            let mut iv = [0; BLOCK_SIZE];
+            key.copy_from_slice(&content[..BLOCK_SIZE]);
+            iv.copy_from_slice(&content[BLOCK_SIZE..]);
+            return Ok(EncryptionKey::create(key, iv));
+        }
+
+        let hex_text: String = String::from_utf8_lossy(&content)
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .collect();
+        if hex_text.len() != BLOCK_SIZE * 4 {
+            return Err(MegError::InvalidKey(format!("Key file {:?} must contain 32 bytes or 64 hex digits",
+                                                    path)));
+        }
+        let (key, iv) = hex_text.split_at(BLOCK_SIZE * 2);
+        EncryptionKey::from_hex(key, iv)
+    }
+
+    pub fn encrypt(&self, data: &[u8]) -> Vec<u8> {
+        let mut buffer = data.to_vec();
+        buffer.resize(padded_size(data.len()), 0);
+
+        let mut encryptor = Aes128CbcEncryptor::new(&self.key.into(), &self.iv.into());
+        for block in buffer.chunks_exact_mut(BLOCK_SIZE) {
+            encryptor.encrypt_block_mut(GenericArray::from_mut_slice(block));
+        }
+        buffer
+    }
+
+    pub fn encrypt_stream<R: Read, W: Write>(&self,
+                                             reader: &mut R,
+                                             writer: &mut W) -> Result<u64, std::io::Error> {
+        let mut encryptor = Aes128CbcEncryptor::new(&self.key.into(), &self.iv.into());
+        let mut buffer = vec![0; STREAM_CHUNK_SIZE];
+        let mut plaintext_size = 0;
+        loop {
+            let filled = fill_from_reader(reader, &mut buffer)?;
+            if filled == 0 {
+                break;
+            }
+            plaintext_size += filled as u64;
+
+            let padded = padded_size(filled);
+            buffer[filled..padded].iter_mut().for_each(|byte| *byte = 0);
+            for block in buffer[..padded].chunks_exact_mut(BLOCK_SIZE) {
+                encryptor.encrypt_block_mut(GenericArray::from_mut_slice(block));
+            }
+            writer.write_all(&buffer[..padded])?;
+
+            if filled < STREAM_CHUNK_SIZE {
+                break;
+            }
+        }
+        Ok(plaintext_size)
+    }
+
+    pub fn decrypt(&self, data: &[u8]) -> Result<Vec<u8>, MegError> {
+        if !data.len().is_multiple_of(BLOCK_SIZE) {
+            return Err(MegError::InvalidEncryptedData { size: data.len() });
+        }
+
+        let mut buffer = data.to_vec();
+        let mut decryptor = Aes128CbcDecryptor::new(&self.key.into(), &self.iv.into());
+        for block in buffer.chunks_exact_mut(BLOCK_SIZE) {
+            decryptor.decrypt_block_mut(GenericArray::from_mut_slice(block));
+        }
+        Ok(buffer)
+    }
+}
+
+// Decrypts a CBC stream chunk by chunk, carrying the cipher state across chunks and
+// dropping the zero padding after the last plaintext byte.
+pub struct DecryptingReader<R: Read>
+{
+    inner: R,
+    decryptor: Aes128CbcDecryptor,
+    buffer: Vec<u8>,
+    position: usize,
+    remaining: u64,
+}
+
+impl<R: Read> DecryptingReader<R>
+{
+    pub fn create(inner: R, key: &EncryptionKey, plaintext_size: u64) -> DecryptingReader<R> {
+        DecryptingReader {
+            inner,
+            decryptor: Aes128CbcDecryptor::new(&key.key.into(), &key.iv.into()),
+            buffer: Vec::with_capacity(STREAM_CHUNK_SIZE),
+            position: 0,
+            remaining: plaintext_size
+        }
+    }
+
+    fn fill_buffer(&mut self) -> Result<(), std::io::Error> {
+        self.buffer.resize(STREAM_CHUNK_SIZE, 0);
+        let filled = fill_from_reader(&mut self.inner, &mut self.buffer)?;
+        if !filled.is_multiple_of(BLOCK_SIZE) {
+            return Err(std::io::Error::new(std::io::ErrorKind::UnexpectedEof,
+                                           "Encrypted entry ends in the middle of an AES block"));
+        }
+
+        self.buffer.truncate(filled);
+        for block in self.buffer.chunks_exact_mut(BLOCK_SIZE) {
+            self.decryptor.decrypt_block_mut(GenericArray::from_mut_slice(block));
+        }
+        self.buffer.truncate(std::cmp::min(filled as u64, self.remaining) as usize);
+        self.remaining -= self.buffer.len() as u64;
+        self.position = 0;
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for DecryptingReader<R> {
+    fn read(&mut self, output: &mut [u8]) -> Result<usize, std::io::Error> {
+        if self.position == self.buffer.len() {
+            if self.remaining == 0 {
+                return Ok(0);
+            }
+            self.fill_buffer()?;
+        }
+
+        let available = std::cmp::min(output.len(), self.buffer.len() - self.position);
+        output[..available].copy_from_slice(&self.buffer[self.position..self.position + available]);
+        self.position += available;
+        Ok(available)
+    }
+}
+
+fn fill_from_reader<R: Read>(reader: &mut R, buffer: &mut [u8]) -> Result<usize, std::io::Error> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match reader.read(&mut buffer[filled..]) {
+            Ok(0) => break,
+            Ok(read) => filled += read,
+            Err(error) if error.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(error) => return Err(error)
+        }
+    }
+    Ok(filled)
+}
+
+pub fn padded_size(size: usize) -> usize {
+    size.div_ceil(BLOCK_SIZE) * BLOCK_SIZE
+}
+
+fn parse_hex_block(hex: &str) -> Result<[u8; BLOCK_SIZE], MegError> {
+    let invalid_hex = || MegError::InvalidKey(format!("'{}' is not {} hex encoded bytes", hex, BLOCK_SIZE));
+    if hex.len() != BLOCK_SIZE * 2 || !hex.is_ascii() {
+        return Err(invalid_hex());
+    }
+
+    let mut block = [0; BLOCK_SIZE];
+    for (i, byte) in block.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).map_err(|_| invalid_hex())?;
+    }
+    Ok(block)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    const KEY_HEX: &str = "30313233343536373839616263646566";
+    const IV_HEX: &str = "66656463626139383736353433323130";
+
+    fn test_key() -> EncryptionKey {
+        EncryptionKey::create(*b"0123456789abcdef", *b"fedcba9876543210")
+    }
+
+    #[test]
+    fn loads_keys_from_hex_and_key_files() {
+        assert_eq!(EncryptionKey::from_hex(KEY_HEX, IV_HEX).unwrap(), test_key());
+        assert!(matches!(EncryptionKey::from_hex("0123", IV_HEX), Err(MegError::InvalidKey(_))));
+        assert!(matches!(EncryptionKey::from_hex(&KEY_HEX.replace('3', "x"), IV_HEX),
+                         Err(MegError::InvalidKey(_))));
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let binary_path = temp_dir.path().join("key.bin");
+        std::fs::write(&binary_path, b"0123456789abcdeffedcba9876543210").unwrap();
+        assert_eq!(EncryptionKey::from_file(&binary_path).unwrap(), test_key());
+
+        let hex_path = temp_dir.path().join("key.txt");
+        std::fs::write(&hex_path, format!("{}\n{}\n", KEY_HEX, IV_HEX)).unwrap();
+        assert_eq!(EncryptionKey::from_file(&hex_path).unwrap(), test_key());
+
+        std::fs::write(&hex_path, "0123").unwrap();
+        assert!(matches!(EncryptionKey::from_file(&hex_path), Err(MegError::InvalidKey(_))));
+    }
+
+    #[test]
+    fn encrypts_and_decrypts_padded_blocks() {
+        let key = test_key();
+        let plaintext = b"DATA\\XML\\UNITS.XML";
+        let encrypted = key.encrypt(plaintext);
+        assert_eq!(encrypted.len(), 32);
+        assert_ne!(&encrypted[..plaintext.len()], plaintext);
+
+        let decrypted = key.decrypt(&encrypted).unwrap();
+        assert_eq!(&decrypted[..plaintext.len()], plaintext);
+        assert!(decrypted[plaintext.len()..].iter().all(|&byte| byte == 0));
+        assert!(matches!(key.decrypt(&encrypted[..20]), Err(MegError::InvalidEncryptedData { size: 20 })));
+    }
+
+    #[test]
+    fn streams_match_whole_buffers() {
+        let key = test_key();
+        let plaintext: Vec<u8> = (0..STREAM_CHUNK_SIZE as u32 + 100).map(|i| (i % 251) as u8).collect();
+
+        let mut encrypted = Vec::new();
+        let written = key.encrypt_stream(&mut plaintext.as_slice(), &mut encrypted).unwrap();
+        assert_eq!(written, plaintext.len() as u64);
+        assert_eq!(encrypted, key.encrypt(&plaintext));
+
+        let mut decrypted = Vec::new();
+        DecryptingReader::create(encrypted.as_slice(), &key, plaintext.len() as u64)
+            .read_to_end(&mut decrypted)
+            .unwrap();
+        assert_eq!(decrypted, plaintext);
+    }
+}
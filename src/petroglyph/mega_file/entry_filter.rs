@@ -0,0 +1,55 @@
+use super::Filename;
+use super::error::MegError;
+
+use glob::{MatchOptions, Pattern};
+
+// Selects entries by exact name or glob pattern. Both patterns and names are normalized
+// first, so matching is case-insensitive and treats '/' and '\' alike. A '*' also matches
+// across separators, which makes '**' redundant.
+pub struct EntryFilter
+{
+    patterns: Vec<(String, Pattern)>
+}
+
+impl EntryFilter
+{
+    pub fn create(patterns: &[String]) -> Result<EntryFilter, MegError> {
+        let patterns = patterns
+            .iter()
+            .map(|pattern| {
+                let normalized = Filename::normalize(pattern).replace("**", "*");
+                Pattern::new(&normalized)
+                    .map(|compiled| (pattern.clone(), compiled))
+                    .map_err(|error| MegError::InvalidPattern { pattern: pattern.clone(),
+                                                                reason: error.msg.to_string() })
+            })
+            .collect::<Result<Vec<(String, Pattern)>, MegError>>()?;
+
+        Ok(EntryFilter { patterns })
+    }
+
+    pub fn matches(&self, internal_file_name: &str) -> bool {
+        self.patterns.is_empty() || !self.matching_patterns(internal_file_name).is_empty()
+    }
+
+    pub fn matching_patterns(&self, internal_file_name: &str) -> Vec<&str> {
+        let normalized = Filename::normalize(internal_file_name);
+        self.patterns
+            .iter()
+            .filter(|(_, pattern)| pattern.matches_with(&normalized, EntryFilter::match_options()))
+            .map(|(original, _)| original.as_str())
+            .collect()
+    }
+
+    pub fn get_patterns(&self) -> impl Iterator<Item = &str> {
+        self.patterns.iter().map(|(original, _)| original.as_str())
+    }
+
+    fn match_options() -> MatchOptions {
+        MatchOptions {
+            case_sensitive: false,
+            require_literal_separator: false,
+            require_literal_leading_dot: false
+        }
+    }
+}
@@ -0,0 +1,36 @@
+use std::path::PathBuf;
+
+const SEPARATORS: &[char] = &['\\', '/'];
+
+// Entry names come from untrusted archives. A name is safe when joining it below the
+// output directory cannot leave that directory on any host OS, which means no NUL bytes,
+// no leading separator, no drive letter and no parent directory components.
+pub fn is_safe(name: &str) -> bool {
+    !name.contains('\0')
+        && !name.starts_with(SEPARATORS)
+        && name.split(SEPARATORS).all(|component| component != ".." && !has_drive_letter(component))
+}
+
+// Drops everything that makes a name unsafe and keeps the remaining components, so
+// "C:\..\DATA\UNITS.XML" becomes "DATA\UNITS.XML".
+pub fn strip(name: &str) -> String {
+    name.replace('\0', "")
+        .split(SEPARATORS)
+        .map(|component| if has_drive_letter(component) { &component[2..] } else { component })
+        .filter(|component| !component.is_empty() && *component != "." && *component != "..")
+        .collect::<Vec<&str>>()
+        .join("\\")
+}
+
+// Archives separate directories with backslashes, but tools on other systems also write
+// forward slashes. Either way every component becomes a real directory below the output.
+pub fn to_relative_path(name: &str) -> PathBuf {
+    name.split(SEPARATORS)
+        .filter(|component| !component.is_empty())
+        .collect()
+}
+
+fn has_drive_letter(component: &str) -> bool {
+    let bytes = component.as_bytes();
+    bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':'
+}
@@ -0,0 +1,57 @@
+use super::TableRecord;
+use super::EncryptionKey;
+use super::encryption::{self, DecryptingReader};
+use super::error::MegError;
+
+use std::io::{Read, Seek, SeekFrom, Take};
+
+pub enum EntryReader<R: Read>
+{
+    Plain(Take<R>),
+    Encrypted(Box<DecryptingReader<Take<R>>>)
+}
+
+impl<R: Read + Seek> EntryReader<R>
+{
+    pub fn create(mut reader: R,
+                  table_record: &TableRecord,
+                  key: Option<&EncryptionKey>) -> Result<EntryReader<R>, MegError> {
+        reader.seek(SeekFrom::Start(table_record.start as u64))?;
+        if !table_record.is_encrypted() {
+            return Ok(EntryReader::Plain(reader.take(table_record.size as u64)));
+        }
+
+        let key = key.ok_or(MegError::MissingKey)?;
+        let stored_size = encryption::padded_size(table_record.size as usize) as u64;
+        Ok(EntryReader::Encrypted(Box::new(DecryptingReader::create(reader.take(stored_size),
+                                                                    key,
+                                                                    table_record.size as u64))))
+    }
+}
+
+impl<R: Read> Read for EntryReader<R> {
+    fn read(&mut self, output: &mut [u8]) -> Result<usize, std::io::Error> {
+        match self {
+            EntryReader::Plain(reader) => reader.read(output),
+            EntryReader::Encrypted(reader) => reader.read(output)
+        }
+    }
+}
+
+// Copies a whole entry to the writer without holding more than one chunk in memory, and
+// fails instead of silently truncating if the archive ends before the entry does.
+pub fn copy_entry<R: Read, W: std::io::Write>(reader: &mut R,
+                                              writer: &mut W,
+                                              expected_size: u64) -> Result<u64, MegError> {
+    let copied = std::io::copy(reader, writer)?;
+    if copied != expected_size {
+        return Err(short_entry_error(expected_size, copied));
+    }
+    Ok(copied)
+}
+
+pub fn short_entry_error(expected_size: u64, copied: u64) -> MegError {
+    MegError::Io(std::io::Error::new(
+        std::io::ErrorKind::UnexpectedEof,
+        format!("Expected {} bytes of entry data but only {} could be read", expected_size, copied)))
+}
@@ -0,0 +1,124 @@
+use std::fmt;
+use std::path::PathBuf;
+
+use super::MegVersion;
+
+#[derive(Debug)]
+pub enum MegError
+{
+    Io(std::io::Error),
+    TruncatedHeader,
+    UnrecognizedHeader { flags: u32, id: u32 },
+    TruncatedTable { table: &'static str },
+    BadNameEncoding { bytes: Vec<u8> },
+    NameIndexOutOfBounds { record: usize, name: u32, num_filenames: u32 },
+    RecordOutOfBounds { name: String, start: u32, size: u32, file_size: u64 },
+    NameTooLong { name: String, length: usize },
+    TooManyEntries { version: MegVersion, count: usize },
+    ArchiveTooLarge { size: u64 },
+    EntryTooLarge { path: PathBuf, size: u64 },
+    EncryptionNotSupported { version: MegVersion },
+    MissingKey,
+    MissingEncryptionKey,
+    InvalidEncryptedData { size: usize },
+    InvalidKey(String),
+    InvalidBaseDirectory(PathBuf),
+    InvalidPattern { pattern: String, reason: String },
+    UnmatchedPatterns(Vec<String>),
+    UnsafeEntryNames(Vec<String>),
+    VerificationFailed { findings: usize },
+    MissingEntries(Vec<String>),
+    MissingArchive(PathBuf),
+    InvalidMegaFilesXml(String),
+    UnsupportedZipMember { name: String, reason: String },
+    MissingOutputFile,
+    DuplicateEntries(Vec<String>),
+    InvalidManifest { path: PathBuf, reason: String },
+    NotReproducible { first: String, second: String },
+    OutputIsInput(PathBuf),
+    UnsupportedInput(PathBuf),
+}
+
+impl fmt::Display for MegError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MegError::Io(error) => write!(f, "I/O error: {}", error),
+            MegError::TruncatedHeader => write!(f, "The archive is too short to hold a MEG header"),
+            MegError::UnrecognizedHeader { flags, id } =>
+                write!(f, "Unrecognized MEG header (first words {:#010X}, {:#010X})", flags, id),
+            MegError::TruncatedTable { table } => write!(f, "The {} table is truncated", table),
+            MegError::BadNameEncoding { bytes } =>
+                write!(f, "Filename {:?} is not valid UTF-8", String::from_utf8_lossy(bytes)),
+            MegError::NameIndexOutOfBounds { record, name, num_filenames } =>
+                write!(f, "Record {} refers to filename {} but the archive only has {} filenames",
+                       record, name, num_filenames),
+            MegError::RecordOutOfBounds { name, start, size, file_size } =>
+                write!(f, "Data of {} ({} bytes at offset {}) lies outside the {} byte archive",
+                       name, size, start, file_size),
+            MegError::NameTooLong { name, length } =>
+                write!(f, "Internal name {} is {} bytes long, the limit is {}", name, length, u16::MAX),
+            MegError::TooManyEntries { version, count } =>
+                write!(f, "A {} archive cannot hold {} entries", version, count),
+            MegError::ArchiveTooLarge { size } =>
+                write!(f, "The archive would be {} bytes, but MEG offsets are limited to {}", size, u32::MAX),
+            MegError::EntryTooLarge { path, size } =>
+                write!(f, "{:?} is {} bytes, but MEG entries are limited to {}", path, size, u32::MAX),
+            MegError::EncryptionNotSupported { version } =>
+                write!(f, "Encryption is only supported by v3 archives, not {}", version),
+            MegError::MissingKey => write!(f, "The archive is encrypted and no key was given"),
+            MegError::MissingEncryptionKey => write!(f, "Encrypting an archive requires a key"),
+            MegError::InvalidEncryptedData { size } =>
+                write!(f, "{} bytes of encrypted data is not a whole number of AES blocks", size),
+            MegError::InvalidKey(reason) => write!(f, "Invalid encryption key: {}", reason),
+            MegError::InvalidBaseDirectory(path) =>
+                write!(f, "Invalid base directory {:?}. Must either be directory or non-existing directory (e.g not file)",
+                       path),
+            MegError::InvalidPattern { pattern, reason } =>
+                write!(f, "Invalid pattern {:?}: {}", pattern, reason),
+            MegError::UnmatchedPatterns(patterns) =>
+                write!(f, "No entries match {}", patterns.join(", ")),
+            MegError::UnsafeEntryNames(names) =>
+                write!(f, "Refusing to extract entries that would be written outside the output directory: {}",
+                       names.iter().map(|name| format!("{:?}", name)).collect::<Vec<String>>().join(", ")),
+            MegError::VerificationFailed { findings } =>
+                write!(f, "Verification found {} problem(s)", findings),
+            MegError::MissingEntries(names) =>
+                write!(f, "The archive has no entries named {}", names.join(", ")),
+            MegError::MissingArchive(path) => write!(f, "Listed archive {:?} does not exist", path),
+            MegError::InvalidMegaFilesXml(reason) => write!(f, "Invalid MegaFiles.xml: {}", reason),
+            MegError::UnsupportedZipMember { name, reason } =>
+                write!(f, "Cannot pack zip member {} because {}", name, reason),
+            MegError::MissingOutputFile => write!(f, "An output file is required when reading from stdin"),
+            MegError::DuplicateEntries(names) =>
+                write!(f, "More than one entry would be named {}", names.join(", ")),
+            MegError::InvalidManifest { path, reason } => write!(f, "Invalid manifest {:?}: {}", path, reason),
+            MegError::NotReproducible { first, second } =>
+                write!(f, "Building twice gave different archives (sha256 {} and {})", first, second),
+            MegError::OutputIsInput(path) => write!(f, "Cannot write {:?} while reading entries from it", path),
+            MegError::UnsupportedInput(path) =>
+                write!(f, "Cannot pack {:?}, expected a directory, a tar, tar.gz, zip or manifest file", path),
+        }
+    }
+}
+
+impl std::error::Error for MegError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            MegError::Io(error) => Some(error),
+            _ => None
+        }
+    }
+}
+
+impl From<std::io::Error> for MegError {
+    fn from(error: std::io::Error) -> MegError {
+        MegError::Io(error)
+    }
+}
+
+pub fn map_eof(error: std::io::Error, truncated: MegError) -> MegError {
+    match error.kind() {
+        std::io::ErrorKind::UnexpectedEof => truncated,
+        _ => MegError::Io(error)
+    }
+}
@@ -0,0 +1,29 @@
+// What to do with entries whose names would resolve outside the output directory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PathPolicy
+{
+    #[default]
+    Reject,
+    Skip,
+    Strip,
+}
+
+impl std::str::FromStr for PathPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<PathPolicy, String> {
+        match s.to_ascii_lowercase().as_str() {
+            "reject" => Ok(PathPolicy::Reject),
+            "skip" => Ok(PathPolicy::Skip),
+            "strip" => Ok(PathPolicy::Strip),
+            _ => Err(format!("Unknown path policy '{}'. Expected one of reject, skip, strip", s))
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ExtractOptions
+{
+    pub patterns: Vec<String>,
+    pub path_policy: PathPolicy,
+}
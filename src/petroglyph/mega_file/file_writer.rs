@@ -1,46 +1,102 @@
 use byteorder::{LittleEndian, WriteBytesExt};
 
-use std::io::{Write, Read};
-use std::fs::File;
+use std::io::{Read, Write};
 
-use super::{ExportFile, TableRecord};
+use super::{EncryptionKey, PackEntry, Header, MegVersion, TableRecord};
+use super::error::MegError;
+use super::entry_reader;
 
-pub fn write_file_names<W: Write>(writer: &mut W, filenames: &Vec<String>) -> usize {
-    filenames.iter().map(|filename|{
-        writer.write_u16::<LittleEndian>(filename.as_bytes().len() as u16).unwrap();
-        writer.write(filename.as_bytes()).unwrap();
+pub fn write_file_names<W: Write>(writer: &mut W, filenames: &[String]) -> Result<usize, MegError> {
+    let mut written = 0;
+    for filename in filenames {
+        if filename.len() > u16::MAX as usize {
+            return Err(MegError::NameTooLong { name: filename.clone(), length: filename.len() });
+        }
+        writer.write_u16::<LittleEndian>(filename.len() as u16)?;
+        writer.write_all(filename.as_bytes())?;
 
-        std::mem::size_of::<u16>() + filename.as_bytes().len()
-    })
-    .sum()
+        written += std::mem::size_of::<u16>() + filename.len();
+    }
+    Ok(written)
+}
+
+pub fn write_encrypted_file_names<W: Write>(writer: &mut W,
+                                            filenames: &[String],
+                                            key: &EncryptionKey) -> Result<usize, MegError> {
+    let mut plain_file_names = Vec::new();
+    write_file_names(&mut plain_file_names, filenames)?;
+
+    let encrypted_file_names = key.encrypt(&plain_file_names);
+    writer.write_all(&encrypted_file_names)?;
+    Ok(encrypted_file_names.len())
 }
 
-pub fn write_header<W: Write>(writer: &mut W, num_filenames: usize, num_files: usize) -> usize {
-    writer.write_u32::<LittleEndian>(num_filenames as u32).unwrap();
-    writer.write_u32::<LittleEndian>(num_files as u32).unwrap();
+pub fn compute_file_names_size(filenames: &[String]) -> usize {
+    filenames.iter()
+             .map(|filename| std::mem::size_of::<u16>() + filename.len())
+             .sum()
+}
+
+pub fn write_header<W: Write>(writer: &mut W, header: &Header) -> Result<usize, MegError> {
+    header.serialize(writer)?;
 
-    std::mem::size_of::<u32>() + std::mem::size_of::<u32>()
+    Ok(header.get_binary_size())
 }
 
 pub fn write_file_table_records<W: Write>(writer: &mut W,
-                                      files: &Vec<ExportFile>) -> Vec<TableRecord> {
+                                      files: &[PackEntry],
+                                      version: MegVersion,
+                                      key: Option<&EncryptionKey>) -> Result<Vec<TableRecord>, MegError> {
     files
         .iter()
-        .map(|export_file|{
-            let table_record = export_file.get_table_record().clone();
-            table_record.serialize(writer);
-            table_record
+        .map(|pack_entry|{
+            let table_record = pack_entry.get_table_record().clone();
+            match (version, key) {
+                (MegVersion::V3, Some(key)) => {
+                    let mut plain_record = Vec::new();
+                    table_record.serialize_v3(&mut plain_record)?;
+                    writer.write_all(&key.encrypt(&plain_record))?;
+                },
+                (MegVersion::V3, None) => table_record.serialize_v3(writer)?,
+                _ => table_record.serialize(writer)?
+            }
+            Ok(table_record)
         })
         .collect()
 }
 
-pub fn write_files<W: Write>(writer: &mut W, files_to_read: &Vec<ExportFile>) {
-    for export_file in files_to_read {
-        let mut file = File::open(&export_file.file_path).unwrap();
-        let mut file_content = Vec::new();
-        file.read_to_end(&mut file_content).unwrap();
+// Expects the entries sorted by start, and fills any gap before an entry with zeros.
+pub fn write_files<W: Write>(writer: &mut W,
+                             files_to_read: &[PackEntry],
+                             position: u64,
+                             key: Option<&EncryptionKey>) -> Result<(), MegError> {
+    let mut position = position;
+    for pack_entry in files_to_read {
+        let start = pack_entry.table_record.start as u64;
+        if start > position {
+            write_padding(writer, start - position)?;
+            position = start;
+        }
+        position += pack_entry.table_record.get_stored_size();
 
-        println!("Writing {} bytes to file {:?}", file_content.len(), export_file.file_path);
-        writer.write(&file_content).unwrap();
+        let expected_size = pack_entry.table_record.size as u64;
+        let mut reader = pack_entry.open()?.take(expected_size);
+        match key {
+            Some(key) if pack_entry.table_record.is_encrypted() => {
+                let written = key.encrypt_stream(&mut reader, writer)?;
+                if written != expected_size {
+                    return Err(entry_reader::short_entry_error(expected_size, written));
+                }
+            },
+            _ => {
+                entry_reader::copy_entry(&mut reader, writer, expected_size)?;
+            }
+        }
     }
+    Ok(())
+}
+
+pub fn write_padding<W: Write>(writer: &mut W, size: u64) -> Result<(), MegError> {
+    std::io::copy(&mut std::io::repeat(0).take(size), writer)?;
+    Ok(())
 }
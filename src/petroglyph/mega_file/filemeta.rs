@@ -1,11 +1,16 @@
 use super::table_record::TableRecord;
 use super::filename::Filename;
 
+use serde::Serialize;
+
 use std::fmt;
 
+#[derive(Serialize)]
 pub struct FileMeta
 {
+    #[serde(rename = "name")]
     pub internal_file_name: Filename,
+    #[serde(flatten)]
     pub table_record: TableRecord,
 }
 
@@ -24,7 +29,7 @@ impl std::fmt::Display for FileMeta {
 impl FileMeta
 {
     pub fn create_from_table_record(table_record: &TableRecord,
-                                filename_list: &Vec<Filename>) -> FileMeta {
+                                filename_list: &[Filename]) -> FileMeta {
         FileMeta {
             internal_file_name: Filename {
                 filename: filename_list[table_record.name as usize].filename.clone()
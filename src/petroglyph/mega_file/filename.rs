@@ -1,9 +1,14 @@
 use byteorder::{LittleEndian, ReadBytesExt};
+use serde::Serialize;
 
 use std::io::Seek;
 use std::io::Read;
-use std::path::PathBuf;
+use std::path::{Component, Path};
 
+use super::error::{self, MegError};
+
+#[derive(Serialize)]
+#[serde(transparent)]
 pub struct Filename
 {
     pub filename: String
@@ -18,27 +23,56 @@ impl Clone for Filename
 
 impl Filename
 {
-    pub fn create_from_cursor<R: Read + Seek>(reader: &mut R) -> Result<Filename, std::io::Error> {
-        let filename_length = reader.read_u16::<LittleEndian>()?;
+    pub fn create_from_cursor<R: Read + Seek>(reader: &mut R) -> Result<Filename, MegError> {
+        let filename_length = reader.read_u16::<LittleEndian>().map_err(truncated_filename_table)?;
 
-        let mut string_buf = Vec::new();
-        string_buf.resize(filename_length as usize, 0);
-        reader.read(&mut string_buf)?;
-        let filename = String::from_utf8(string_buf).unwrap();
+        let mut string_buf = vec![0; filename_length as usize];
+        reader.read_exact(&mut string_buf).map_err(truncated_filename_table)?;
+        let filename = String::from_utf8(string_buf)
+            .map_err(|error| MegError::BadNameEncoding { bytes: error.into_bytes() })?;
 
         Ok( Filename{ filename } )
     }
 
-    pub fn from_path(path: &PathBuf) -> Filename{
-        Filename{ filename: path.components()
-                                .map(|path_comp| Filename::path_component_as_str(path_comp))
-                                .collect::<Vec<&str>>()
-                                .join("\\") }
+    // The game looks entries up by their upper case name with backslash separators. It only
+    // upper cases ASCII letters, so other characters keep their length and their CRC.
+    pub fn normalize(name: &str) -> String {
+        name.replace('/', "\\").to_ascii_uppercase()
+    }
+
+    // Only the normal components are kept and joined with backslashes whatever the host OS
+    // uses, so the same tree packs to the same names everywhere.
+    pub fn from_path(path: &Path) -> Result<Filename, MegError> {
+        let components = path.components()
+                             .filter(|component| matches!(component, Component::Normal(_)))
+                             .map(Filename::path_component_as_str)
+                             .collect::<Result<Vec<&str>, MegError>>()?;
+        Ok(Filename{ filename: Filename::normalize(&components.join("\\")) })
+    }
+
+    // Names of packed files are relative to the directory being packed, so the archive does
+    // not depend on where that directory lives, optionally mounted below a prefix like DATA\.
+    pub fn from_relative_path(path: &Path, root: &Path, mount_prefix: &str) -> Result<Filename, MegError> {
+        let relative_path = path.strip_prefix(root).unwrap_or(path);
+        let relative_name = Filename::from_path(relative_path)?.filename;
+        let mount_prefix = Filename::normalize(mount_prefix);
+        let mount_prefix = mount_prefix.trim_matches('\\');
+
+        if mount_prefix.is_empty() {
+            Ok(Filename{ filename: relative_name })
+        }
+        else {
+            Ok(Filename{ filename: format!("{}\\{}", mount_prefix, relative_name) })
+        }
     }
 
-    fn path_component_as_str(path_component: std::path::Component<'_>) -> &str {
-        return path_component.as_os_str()
-                             .to_str()
-                             .unwrap_or_default()
+    fn path_component_as_str(path_component: Component<'_>) -> Result<&str, MegError> {
+        let component = path_component.as_os_str();
+        component.to_str()
+                 .ok_or_else(|| MegError::BadNameEncoding { bytes: component.as_encoded_bytes().to_vec() })
     }
 }
+
+fn truncated_filename_table(error: std::io::Error) -> MegError {
+    error::map_eof(error, MegError::TruncatedTable { table: "filename" })
+}
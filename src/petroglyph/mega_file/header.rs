@@ -1,26 +1,346 @@
-use std::io::Read;
+use std::io::{Read, Seek, SeekFrom, Write};
 
-use byteorder::{LittleEndian, ReadBytesExt};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use serde::{Serialize, Serializer};
 
+use super::TableRecord;
+use super::error::{self, MegError};
+
+pub const HEADER_FLAGS_UNENCRYPTED: u32 = 0xFFFFFFFF;
+pub const HEADER_FLAGS_ENCRYPTED: u32 = 0x8FFFFFFF;
+pub const HEADER_ID: u32 = 0x3F7D70A4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MegVersion
+{
+    V1,
+    V2,
+    V3,
+}
+
+impl std::fmt::Display for MegVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MegVersion::V1 => write!(f, "v1"),
+            MegVersion::V2 => write!(f, "v2"),
+            MegVersion::V3 => write!(f, "v3"),
+        }
+    }
+}
+
+impl Serialize for MegVersion {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl std::str::FromStr for MegVersion {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<MegVersion, String> {
+        match s.to_ascii_lowercase().as_str() {
+            "1" | "v1" => Ok(MegVersion::V1),
+            "2" | "v2" => Ok(MegVersion::V2),
+            "3" | "v3" => Ok(MegVersion::V3),
+            _ => Err(format!("Unknown MEG version '{}'. Expected one of v1, v2, v3", s))
+        }
+    }
+}
+
+// Version 1 headers only hold the two counts. Version 2 headers are prefixed by the
+// 0xFFFFFFFF marker and the 0x3F7D70A4 id, and store the offset of the first data byte
+// before the counts. Version 3 headers use 0x8FFFFFFF as marker when the archive is
+// encrypted and append the byte size of the filename table.
+#[derive(Debug, Clone, Serialize)]
 pub struct Header
 {
+    pub version: MegVersion,
+    pub encrypted: bool,
+    pub data_start: u32,
     pub num_filenames: u32,
     pub num_files: u32,
+    pub filename_table_size: u32,
 }
 
 impl Header
 {
     pub fn create(num_filenames: u32, num_files: u32) -> Header {
         Header{
+            version: MegVersion::V1,
+            encrypted: false,
+            data_start: 0,
+            num_filenames,
+            num_files,
+            filename_table_size: 0
+        }
+    }
+
+    pub fn create_v2(num_filenames: u32, num_files: u32, data_start: u32) -> Header {
+        Header{
+            version: MegVersion::V2,
+            encrypted: false,
+            data_start,
+            num_filenames,
+            num_files,
+            filename_table_size: 0
+        }
+    }
+
+    pub fn create_v3(num_filenames: u32,
+                     num_files: u32,
+                     data_start: u32,
+                     filename_table_size: u32,
+                     encrypted: bool) -> Header {
+        Header{
+            version: MegVersion::V3,
+            encrypted,
+            data_start,
             num_filenames,
-            num_files
+            num_files,
+            filename_table_size
         }
     }
 
-    pub fn create_from_cursor<R: Read>(reader: &mut R) -> Result<Header, std::io::Error> {
-        let num_filenames = reader.read_u32::<LittleEndian>()?;
-        let num_files = reader.read_u32::<LittleEndian>()?;
+    pub fn create_from_cursor<R: Read>(reader: &mut R) -> Result<Header, MegError> {
+        let num_filenames = reader.read_u32::<LittleEndian>().map_err(truncated_header)?;
+        let num_files = reader.read_u32::<LittleEndian>().map_err(truncated_header)?;
+
+        Ok(Header::create(num_filenames, num_files))
+    }
+
+    pub fn create_v2_from_cursor<R: Read>(reader: &mut R) -> Result<Header, MegError> {
+        let flags = reader.read_u32::<LittleEndian>().map_err(truncated_header)?;
+        let id = reader.read_u32::<LittleEndian>().map_err(truncated_header)?;
+        if flags != HEADER_FLAGS_UNENCRYPTED || id != HEADER_ID {
+            return Err(MegError::UnrecognizedHeader { flags, id });
+        }
+
+        let data_start = reader.read_u32::<LittleEndian>().map_err(truncated_header)?;
+        let num_filenames = reader.read_u32::<LittleEndian>().map_err(truncated_header)?;
+        let num_files = reader.read_u32::<LittleEndian>().map_err(truncated_header)?;
+
+        Ok(Header::create_v2(num_filenames, num_files, data_start))
+    }
+
+    pub fn create_v3_from_cursor<R: Read>(reader: &mut R) -> Result<Header, MegError> {
+        let flags = reader.read_u32::<LittleEndian>().map_err(truncated_header)?;
+        let id = reader.read_u32::<LittleEndian>().map_err(truncated_header)?;
+        let encrypted = flags == HEADER_FLAGS_ENCRYPTED;
+        if (flags != HEADER_FLAGS_UNENCRYPTED && !encrypted) || id != HEADER_ID {
+            return Err(MegError::UnrecognizedHeader { flags, id });
+        }
+
+        let data_start = reader.read_u32::<LittleEndian>().map_err(truncated_header)?;
+        let num_filenames = reader.read_u32::<LittleEndian>().map_err(truncated_header)?;
+        let num_files = reader.read_u32::<LittleEndian>().map_err(truncated_header)?;
+        let filename_table_size = reader.read_u32::<LittleEndian>().map_err(truncated_header)?;
+
+        Ok(Header::create_v3(num_filenames, num_files, data_start, filename_table_size, encrypted))
+    }
+
+    pub fn create_versioned_from_cursor<R: Read>(reader: &mut R,
+                                                 version: MegVersion) -> Result<Header, MegError> {
+        match version {
+            MegVersion::V1 => Header::create_from_cursor(reader),
+            MegVersion::V2 => Header::create_v2_from_cursor(reader),
+            MegVersion::V3 => Header::create_v3_from_cursor(reader),
+        }
+    }
+
+    // Version 2 and 3 archives share the leading marker and id, and unencrypted ones are
+    // told apart by checking which layout makes the data start offset add up.
+    pub fn detect_version<R: Read + Seek>(reader: &mut R) -> Result<MegVersion, MegError> {
+        let file_size = reader.seek(SeekFrom::End(0))?;
+        reader.seek(SeekFrom::Start(0))?;
+        let flags = reader.read_u32::<LittleEndian>().map_err(truncated_header)?;
+        let id = reader.read_u32::<LittleEndian>().map_err(truncated_header)?;
+        let version = Header::detect_version_from_words(reader, file_size, flags, id);
+        reader.seek(SeekFrom::Start(0))?;
+
+        version
+    }
+
+    fn detect_version_from_words<R: Read>(reader: &mut R,
+                                          file_size: u64,
+                                          flags: u32,
+                                          id: u32) -> Result<MegVersion, MegError> {
+        if id == HEADER_ID && flags == HEADER_FLAGS_ENCRYPTED {
+            return Ok(MegVersion::V3);
+        }
+
+        if id == HEADER_ID && flags == HEADER_FLAGS_UNENCRYPTED {
+            let data_start = reader.read_u32::<LittleEndian>().map_err(truncated_header)? as u64;
+            let _num_filenames = reader.read_u32::<LittleEndian>().map_err(truncated_header)?;
+            let num_files = reader.read_u32::<LittleEndian>().map_err(truncated_header)? as u64;
+            let filename_table_size = match reader.read_u32::<LittleEndian>() {
+                Ok(filename_table_size) => filename_table_size as u64,
+                Err(_) => return Ok(MegVersion::V2)
+            };
+
+            let v3_data_start = Header::get_binary_size_for_version(MegVersion::V3) as u64
+                + filename_table_size
+                + num_files * TableRecord::get_binary_size_for_version(MegVersion::V3) as u64;
+            if data_start == v3_data_start && data_start <= file_size {
+                return Ok(MegVersion::V3);
+            }
+            return Ok(MegVersion::V2);
+        }
+
+        let (num_filenames, num_files) = (flags as u64, id as u64);
+        let minimal_size = Header::get_binary_size_for_version(MegVersion::V1) as u64
+            + num_filenames * std::mem::size_of::<u16>() as u64
+            + num_files * TableRecord::get_binary_size_for_version(MegVersion::V1) as u64;
+        if minimal_size > file_size {
+            return Err(MegError::UnrecognizedHeader { flags, id });
+        }
+        Ok(MegVersion::V1)
+    }
+
+    pub fn serialize<W: Write>(&self, writer: &mut W) -> Result<(), MegError> {
+        if self.version != MegVersion::V1 {
+            let flags = if self.encrypted { HEADER_FLAGS_ENCRYPTED } else { HEADER_FLAGS_UNENCRYPTED };
+            writer.write_u32::<LittleEndian>(flags)?;
+            writer.write_u32::<LittleEndian>(HEADER_ID)?;
+            writer.write_u32::<LittleEndian>(self.data_start)?;
+        }
+        writer.write_u32::<LittleEndian>(self.num_filenames)?;
+        writer.write_u32::<LittleEndian>(self.num_files)?;
+        if self.version == MegVersion::V3 {
+            writer.write_u32::<LittleEndian>(self.filename_table_size)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn get_binary_size(&self) -> usize {
+        Header::get_binary_size_for_version(self.version)
+    }
+
+    pub fn get_binary_size_for_version(version: MegVersion) -> usize {
+        match version {
+            MegVersion::V1 => std::mem::size_of::<u32>() * 2,
+            MegVersion::V2 => std::mem::size_of::<u32>() * 5,
+            MegVersion::V3 => std::mem::size_of::<u32>() * 6,
+        }
+    }
+}
+
+impl std::fmt::Display for Header {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "encrypted={}, num_filenames={}, num_files={}",
+               self.encrypted, self.num_filenames, self.num_files)?;
+        if self.version != MegVersion::V1 {
+            write!(f, ", data_start={}", self.data_start)?;
+        }
+        if self.version == MegVersion::V3 {
+            write!(f, ", filename_table_size={}", self.filename_table_size)?;
+        }
+        Ok(())
+    }
+}
+
+fn truncated_header(error: std::io::Error) -> MegError {
+    error::map_eof(error, MegError::TruncatedHeader)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    use std::io::Cursor;
+
+    fn serialized(header: &Header) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        header.serialize(&mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn v2_header_round_trips() {
+        let header = Header::create_v2(3, 3, 120);
+        let bytes = serialized(&header);
+        assert_eq!(bytes.len(), header.get_binary_size());
+        assert_eq!(&bytes[..8], &[0xFF, 0xFF, 0xFF, 0xFF, 0xA4, 0x70, 0x7D, 0x3F]);
+
+        let read = Header::create_v2_from_cursor(&mut Cursor::new(bytes)).unwrap();
+        assert_eq!(read.version, MegVersion::V2);
+        assert_eq!((read.data_start, read.num_filenames, read.num_files), (120, 3, 3));
+    }
+
+    #[test]
+    fn v1_header_is_only_the_counts() {
+        let bytes = serialized(&Header::create(2, 2));
+        assert_eq!(bytes, [2, 0, 0, 0, 2, 0, 0, 0]);
+
+        let read = Header::create_from_cursor(&mut Cursor::new(bytes)).unwrap();
+        assert_eq!((read.version, read.num_filenames, read.num_files), (MegVersion::V1, 2, 2));
+    }
+
+    #[test]
+    fn v3_header_round_trips_with_encryption_flag() {
+        for encrypted in [false, true] {
+            let header = Header::create_v3(2, 2, 100, 40, encrypted);
+            let bytes = serialized(&header);
+            assert_eq!(bytes.len(), 24);
+            let flags = if encrypted { HEADER_FLAGS_ENCRYPTED } else { HEADER_FLAGS_UNENCRYPTED };
+            assert_eq!(&bytes[..4], &flags.to_le_bytes());
+
+            let read = Header::create_v3_from_cursor(&mut Cursor::new(bytes)).unwrap();
+            assert_eq!(read.encrypted, encrypted);
+            assert_eq!((read.data_start, read.num_files, read.filename_table_size), (100, 2, 40));
+        }
+    }
+
+    #[test]
+    fn v2_reader_rejects_other_headers() {
+        let bytes = serialized(&Header::create(2, 2));
+        assert!(matches!(Header::create_v2_from_cursor(&mut Cursor::new(bytes)),
+                         Err(MegError::UnrecognizedHeader { .. })));
+        assert!(matches!(Header::create_v2_from_cursor(&mut Cursor::new(vec![0xFF; 6])),
+                         Err(MegError::TruncatedHeader)));
+    }
+
+    // Pads a serialized header out to the given file size.
+    fn archive_bytes(header: &Header, file_size: usize) -> Cursor<Vec<u8>> {
+        let mut bytes = serialized(header);
+        bytes.resize(file_size, 0);
+        Cursor::new(bytes)
+    }
+
+    #[test]
+    fn detects_v1_from_plausible_counts() {
+        let mut archive = archive_bytes(&Header::create(1, 1), 8 + 2 + 20);
+        assert_eq!(Header::detect_version(&mut archive).unwrap(), MegVersion::V1);
+        assert_eq!(archive.position(), 0);
+
+        let mut too_short = archive_bytes(&Header::create(1000, 1000), 64);
+        assert!(matches!(Header::detect_version(&mut too_short), Err(MegError::UnrecognizedHeader { .. })));
+    }
+
+    #[test]
+    fn tells_unencrypted_v3_from_v2_by_data_start() {
+        // One entry named "A": 2 + 1 bytes of filename table.
+        let v3_data_start = 24 + 3 + 20;
+        let mut v3 = archive_bytes(&Header::create_v3(1, 1, v3_data_start, 3, false), v3_data_start as usize + 4);
+        assert_eq!(Header::detect_version(&mut v3).unwrap(), MegVersion::V3);
+
+        let v2_data_start = 20 + 3 + 20;
+        let mut v2 = archive_bytes(&Header::create_v2(1, 1, v2_data_start), v2_data_start as usize + 4);
+        assert_eq!(Header::detect_version(&mut v2).unwrap(), MegVersion::V2);
+
+        let mut empty_v2 = archive_bytes(&Header::create_v2(0, 0, 20), 20);
+        assert_eq!(Header::detect_version(&mut empty_v2).unwrap(), MegVersion::V2);
+    }
+
+    #[test]
+    fn encrypted_marker_means_v3() {
+        let mut archive = archive_bytes(&Header::create_v3(1, 1, 80, 16, true), 100);
+        assert_eq!(Header::detect_version(&mut archive).unwrap(), MegVersion::V3);
+    }
 
-        Ok(Header{ num_filenames, num_files })
+    #[test]
+    fn detection_needs_the_leading_words() {
+        assert!(matches!(Header::detect_version(&mut Cursor::new(vec![0; 6])), Err(MegError::TruncatedHeader)));
     }
 }
@@ -0,0 +1,68 @@
+use serde::Deserialize;
+
+use super::{DataOrder, Filename, MegError, PackEntry};
+
+use std::path::{Path, PathBuf};
+
+// Lists the files to pack, in TOML:
+//
+//     data_order = "manifest"
+//
+//     [[files]]
+//     source = "art/units/xwing.dds"
+//     name = "DATA\ART\TEXTURES\XWING.DDS"
+//
+// or the same structure in JSON. Sources are relative to the manifest's directory, and an
+// entry without a name is named after its source path like a packed directory would be.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest
+{
+    #[serde(default)]
+    pub data_order: Option<DataOrder>,
+    pub files: Vec<ManifestFile>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ManifestFile
+{
+    pub source: PathBuf,
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+impl Manifest
+{
+    // The format follows the extension, .json for JSON and anything else for TOML.
+    pub fn from_file(path: &Path) -> Result<Manifest, MegError> {
+        let content = std::fs::read_to_string(path)?;
+        let invalid_manifest = |reason: String| MegError::InvalidManifest { path: path.to_path_buf(), reason };
+
+        let is_json = path.extension()
+                          .and_then(|extension| extension.to_str())
+                          .is_some_and(|extension| extension.eq_ignore_ascii_case("json"));
+        if is_json {
+            serde_json::from_str(&content).map_err(|error| invalid_manifest(error.to_string()))
+        }
+        else {
+            toml::from_str(&content).map_err(|error| invalid_manifest(error.to_string()))
+        }
+    }
+
+    // Entries keep the order of the manifest, which is the order their data is laid out in
+    // when the manifest asks for it.
+    pub fn get_pack_entries(&self, base_directory: &Path, mount_prefix: &str) -> Result<Vec<PackEntry>, MegError> {
+        self.files
+            .iter()
+            .map(|manifest_file| {
+                let source = base_directory.join(&manifest_file.source);
+                let name = match &manifest_file.name {
+                    Some(name) => Filename::normalize(name),
+                    None => Filename::from_relative_path(&manifest_file.source, Path::new(""), mount_prefix)?.filename
+                };
+                PackEntry::from_path(&source, name)
+            })
+            .collect()
+    }
+}
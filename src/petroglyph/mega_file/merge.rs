@@ -0,0 +1,45 @@
+use super::{MegaFile, MegError, PackEntry};
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+pub enum MergeSource<'a>
+{
+    Archive(&'a MegaFile),
+    Directory(&'a Path),
+}
+
+// An entry of source `overridden` that was replaced by the entry with the same normalized
+// name in the later source `winner`. Sources are numbered by their position in the merge.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Override
+{
+    pub name: String,
+    pub overridden: usize,
+    pub winner: usize,
+}
+
+// Later sources win. Pack entry names are already normalized, so keying on them matches
+// entries the way the game resolves them.
+pub fn collect_entries(sources: &[MergeSource<'_>],
+                       mount_prefix: &str) -> Result<(Vec<PackEntry>, Vec<Override>), MegError> {
+    let mut entries: BTreeMap<String, (usize, PackEntry)> = BTreeMap::new();
+    let mut overrides = Vec::new();
+
+    for (source_index, source) in sources.iter().enumerate() {
+        let source_entries = match source {
+            MergeSource::Archive(mega_file) => mega_file.get_pack_entries()?,
+            MergeSource::Directory(directory) =>
+                MegaFile::get_files_to_zip_from_directory_sorted(directory, mount_prefix)?,
+        };
+
+        for pack_entry in source_entries {
+            let name = pack_entry.internal_file_name.clone();
+            if let Some((overridden, _pack_entry)) = entries.insert(name.clone(), (source_index, pack_entry)) {
+                overrides.push(Override { name, overridden, winner: source_index });
+            }
+        }
+    }
+
+    Ok((entries.into_values().map(|(_source_index, pack_entry)| pack_entry).collect(), overrides))
+}
@@ -1,79 +1,362 @@
-pub mod filename;
-pub mod table_record;
-pub mod filemeta;
-pub mod export_file;
-pub mod header;
-pub mod file_writer;
+mod filename;
+mod table_record;
+mod filemeta;
+mod export_file;
+mod header;
+mod file_writer;
+mod create_options;
+mod encryption;
+mod pack_entry;
+mod error;
+mod entry_reader;
+mod entry_filter;
+mod extract_options;
+mod entry_path;
+mod verification;
+mod update_options;
+mod content_hash;
+mod archive_diff;
+mod merge;
+mod vfs;
+mod tar_export;
+mod archive_input;
+mod manifest;
+mod create_input;
 
 pub use filename::Filename;
 pub use table_record::TableRecord;
 pub use filemeta::FileMeta;
 pub use export_file::ExportFile;
-pub use header::Header;
+pub use header::{Header, MegVersion};
+pub use create_options::{CreateOptions, DataOrder};
+pub use encryption::EncryptionKey;
+pub use pack_entry::{PackEntry, EntrySource};
+pub use error::MegError;
+pub use entry_reader::EntryReader;
+pub use entry_filter::EntryFilter;
+pub use extract_options::{ExtractOptions, PathPolicy};
+pub use verification::Finding;
+pub use update_options::UpdateOptions;
+pub use content_hash::{ContentHash, HashWriter};
+pub use archive_diff::EntryChange;
+pub use merge::{MergeSource, Override};
+pub use vfs::{Vfs, VfsSource};
+pub use manifest::{Manifest, ManifestFile};
+pub use create_input::CreateInput;
+
+pub mod crc;
 
 mod osext;
-mod crc;
 
+use std::cmp::Reverse;
+use std::collections::HashMap;
 use std::fs::File;
-use std::path::PathBuf;
+use std::io::{BufWriter, Cursor, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 pub struct MegaFile
 {
     file: File,
-    _header: Header,
+    path: PathBuf,
+    header: Header,
     filename_table: Vec<Filename>,
-    table_records: Vec<TableRecord>
+    table_records: Vec<TableRecord>,
+    sorted_by_crc: bool,
+    encryption: Option<EncryptionKey>
 }
 
 impl<'a> MegaFile
 {
-    pub fn create(path: &PathBuf) -> Result<MegaFile, std::io::Error> {
-        let mut file = File::open(path)?;
+    pub fn create(path: &Path) -> Result<MegaFile, MegError> {
+        MegaFile::read_archive(path, None, None)
+    }
 
-        let header = Header::create_from_cursor(&mut file)?;
+    pub fn create_with_key(path: &Path, key: EncryptionKey) -> Result<MegaFile, MegError> {
+        MegaFile::read_archive(path, None, Some(key))
+    }
 
-        let filename_table = (0..header.num_filenames)
-            .into_iter()
-            .map(|_i| { Filename::create_from_cursor(&mut file).unwrap() })
-            .collect();
+    pub fn create_with_version(path: &Path,
+                               version: MegVersion,
+                               key: Option<EncryptionKey>) -> Result<MegaFile, MegError> {
+        MegaFile::read_archive(path, Some(version), key)
+    }
 
-        let table_records = (0..header.num_files)
-            .into_iter()
-            .map(|_i| { TableRecord::create_from_cursor(&mut file).unwrap() })
-            .collect();
+    fn read_archive(path: &Path,
+                    version: Option<MegVersion>,
+                    key: Option<EncryptionKey>) -> Result<MegaFile, MegError> {
+        let mega_file = MegaFile::read_tables(path, version, key)?;
+        MegaFile::validate_table_records(&mega_file.table_records,
+                                         &mega_file.filename_table,
+                                         mega_file.file.metadata()?.len())?;
+        Ok(mega_file)
+    }
+
+    // Reads the tables without checking that the records make sense, for verification.
+    fn read_tables(path: &Path,
+                   version: Option<MegVersion>,
+                   key: Option<EncryptionKey>) -> Result<MegaFile, MegError> {
+        let mut file = File::open(path)?;
+
+        let version = match version {
+            Some(version) => version,
+            None => Header::detect_version(&mut file)?
+        };
+        let header = Header::create_versioned_from_cursor(&mut file, version)?;
+        if header.encrypted && key.is_none() {
+            return Err(MegError::MissingKey);
+        }
+
+        let filename_table = MegaFile::read_filename_table(&mut file, &header, key.as_ref())?;
+        let table_records = MegaFile::read_table_records(&mut file, &header, key.as_ref())?;
 
         Ok(MegaFile{
                file,
-               _header: header,
+               path: path.to_path_buf(),
+               header,
                filename_table,
-               table_records
+               sorted_by_crc: MegaFile::is_sorted_by_crc(&table_records),
+               table_records,
+               encryption: key
         })
     }
 
-    pub fn extract_files_to(&self, base_directory: &PathBuf) -> Result<(), std::io::Error> {
-        MegaFile::prepare_extraction_directory(&base_directory)?;
+    fn read_filename_table(file: &mut File,
+                           header: &Header,
+                           key: Option<&EncryptionKey>) -> Result<Vec<Filename>, MegError> {
+        if header.version != MegVersion::V3 {
+            return (0..header.num_filenames)
+                .map(|_i| Filename::create_from_cursor(file))
+                .collect();
+        }
+
+        let mut filename_table_content = Vec::new();
+        file.take(header.filename_table_size as u64).read_to_end(&mut filename_table_content)?;
+        if filename_table_content.len() != header.filename_table_size as usize {
+            return Err(MegError::TruncatedTable { table: "filename" });
+        }
+        if let (true, Some(key)) = (header.encrypted, key) {
+            filename_table_content = key.decrypt(&filename_table_content)?;
+        }
+
+        let mut cursor = Cursor::new(filename_table_content);
+        (0..header.num_filenames)
+            .map(|_i| Filename::create_from_cursor(&mut cursor))
+            .collect()
+    }
+
+    fn read_table_records(file: &mut File,
+                          header: &Header,
+                          key: Option<&EncryptionKey>) -> Result<Vec<TableRecord>, MegError> {
+        (0..header.num_files)
+            .map(|_i| match (header.version, header.encrypted, key) {
+                (MegVersion::V3, true, Some(key)) => {
+                    let record_size = TableRecord::get_binary_size_for_version(MegVersion::V3);
+                    let mut encrypted_record = vec![0; encryption::padded_size(record_size)];
+                    file.read_exact(&mut encrypted_record)
+                        .map_err(|error| error::map_eof(error, MegError::TruncatedTable { table: "file" }))?;
+                    TableRecord::create_v3_from_cursor(&mut Cursor::new(key.decrypt(&encrypted_record)?))
+                },
+                (MegVersion::V3, _, _) => TableRecord::create_v3_from_cursor(file),
+                _ => TableRecord::create_from_cursor(file)
+            })
+            .collect()
+    }
+
+    fn validate_table_records(table_records: &[TableRecord],
+                              filename_table: &[Filename],
+                              file_size: u64) -> Result<(), MegError> {
+        for (i, table_record) in table_records.iter().enumerate() {
+            let filename = filename_table.get(table_record.name as usize).ok_or(
+                MegError::NameIndexOutOfBounds { record: i,
+                                                 name: table_record.name,
+                                                 num_filenames: filename_table.len() as u32 })?;
+
+            if table_record.start as u64 + table_record.get_stored_size() > file_size {
+                return Err(MegError::RecordOutOfBounds { name: filename.filename.clone(),
+                                                         start: table_record.start,
+                                                         size: table_record.size,
+                                                         file_size });
+            }
+        }
+        Ok(())
+    }
+
+    // Opens an archive that may fail the checks done when opening it normally, and reports
+    // everything found wrong with it.
+    pub fn verify_archive(path: &Path,
+                          version: Option<MegVersion>,
+                          key: Option<EncryptionKey>) -> Result<Vec<Finding>, MegError> {
+        MegaFile::read_tables(path, version, key)?.verify()
+    }
+
+    pub fn verify(&self) -> Result<Vec<Finding>, MegError> {
+        Ok(verification::verify(&self.header,
+                                &self.filename_table,
+                                &self.table_records,
+                                self.file.metadata()?.len()))
+    }
+
+    fn is_sorted_by_crc(table_records: &[TableRecord]) -> bool {
+        table_records.windows(2).all(|pair| pair[0].crc <= pair[1].crc)
+    }
+
+    // Looks an entry up the way the game does: by the CRC of the normalized path, using a
+    // binary search over the CRC sorted records and comparing names to resolve collisions.
+    // Archives from other tools whose records are not sorted fall back to a linear scan.
+    pub fn find(&self, path: &str) -> Option<FileMeta> {
+        let normalized = Filename::normalize(path);
+        let crc = crc::crc32::compute_from_bytes(normalized.as_bytes());
+
+        let candidates = if self.sorted_by_crc {
+            let first = self.table_records.partition_point(|table_record| table_record.crc < crc);
+            let last = self.table_records.partition_point(|table_record| table_record.crc <= crc);
+            &self.table_records[first..last]
+        }
+        else {
+            &self.table_records[..]
+        };
+
+        candidates
+            .iter()
+            .find(|table_record| {
+                table_record.crc == crc
+                    && Filename::normalize(&self.filename_table[table_record.name as usize].filename) == normalized
+            })
+            .map(|table_record| FileMeta::create_from_table_record(table_record, &self.filename_table))
+    }
+
+    pub fn open_entry(&self, table_record: &TableRecord) -> Result<EntryReader<File>, MegError> {
+        EntryReader::create(self.file.try_clone()?, table_record, self.encryption.as_ref())
+    }
+
+    pub fn hash_entry(&self, table_record: &TableRecord) -> Result<ContentHash, MegError> {
+        ContentHash::from_reader(&mut self.open_entry(table_record)?, table_record.size as u64)
+    }
+
+    pub fn diff(&self, other: &MegaFile) -> Result<Vec<EntryChange>, MegError> {
+        archive_diff::diff(self, other)
+    }
+
+    pub fn get_version(&self) -> MegVersion {
+        self.header.version
+    }
+
+    pub fn get_header(&self) -> &Header {
+        &self.header
+    }
+
+    // Version 1 headers do not store where the data starts, so it is computed from the tables.
+    pub fn get_data_start(&self) -> u64 {
+        verification::compute_tables_end(&self.header, &self.filename_table)
+    }
+
+    // Bytes a deduplicated archive saves by storing data shared by several entries only once.
+    pub fn get_shared_data_size(&self) -> u64 {
+        let mut ranges: Vec<(u32, u64)> = self.table_records
+                                              .iter()
+                                              .map(|table_record| (table_record.start, table_record.get_stored_size()))
+                                              .collect();
+        let total_size: u64 = ranges.iter().map(|(_start, size)| size).sum();
+        ranges.sort();
+        ranges.dedup();
+        total_size - ranges.iter().map(|(_start, size)| size).sum::<u64>()
+    }
+
+    pub fn extract_files_to(&self, base_directory: &Path) -> Result<(), MegError> {
+        self.extract_files_with_options(base_directory, &ExtractOptions::default())
+    }
+
+    pub fn extract_files_with_options(&self,
+                                      base_directory: &Path,
+                                      options: &ExtractOptions) -> Result<(), MegError> {
+        let export_files = self.select_export_files(options)?;
+
+        MegaFile::prepare_extraction_directory(base_directory)?;
 
-        for export_file in self.get_export_file_iterator() {
-            println!("Export file: {:?}", export_file.file_path);
+        for export_file in export_files {
+            println!("Export file: {}", export_file.internal_file_name);
 
             let output_path = base_directory.join(&export_file.file_path);
-            let mut read_file_handle = self.file.try_clone()?;
-            export_file.extract_to_file(&mut read_file_handle, &output_path)?;
+            let read_file_handle = self.file.try_clone()?;
+            export_file.extract_to_file(read_file_handle, &output_path, self.encryption.as_ref())?;
         }
 
         Ok(())
     }
 
-    fn prepare_extraction_directory(base_directory: &PathBuf) -> Result<(), std::io::Error> {
+    // Streams the selected entries into a tar archive without touching the file system, and
+    // hands the writer back once the archive is finished.
+    pub fn export_tar<W: Write>(&self, writer: W, options: &ExtractOptions) -> Result<W, MegError> {
+        let export_files = self.select_export_files(options)?;
+        tar_export::write_tar(writer, &export_files, &self.file, self.encryption.as_ref())
+    }
+
+    fn select_export_files(&self, options: &ExtractOptions) -> Result<Vec<ExportFile>, MegError> {
+        let entry_filter = EntryFilter::create(&options.patterns)?;
+        let export_files: Vec<ExportFile> = self.get_export_file_iterator()
+            .filter(|export_file| entry_filter.matches(&export_file.internal_file_name))
+            .collect();
+
+        let unmatched_patterns: Vec<String> = entry_filter
+            .get_patterns()
+            .filter(|pattern| !export_files.iter().any(|export_file| {
+                entry_filter.matching_patterns(&export_file.internal_file_name).contains(pattern)
+            }))
+            .map(String::from)
+            .collect();
+        if !unmatched_patterns.is_empty() {
+            return Err(MegError::UnmatchedPatterns(unmatched_patterns));
+        }
+        MegaFile::apply_path_policy(export_files, options.path_policy)
+    }
+
+    fn apply_path_policy(export_files: Vec<ExportFile>,
+                         path_policy: PathPolicy) -> Result<Vec<ExportFile>, MegError> {
+        let unsafe_names: Vec<String> = export_files
+            .iter()
+            .filter(|export_file| !entry_path::is_safe(&export_file.internal_file_name))
+            .map(|export_file| export_file.internal_file_name.clone())
+            .collect();
+        if unsafe_names.is_empty() {
+            return Ok(export_files);
+        }
+
+        match path_policy {
+            PathPolicy::Reject => Err(MegError::UnsafeEntryNames(unsafe_names)),
+            PathPolicy::Skip => {
+                for name in &unsafe_names {
+                    eprintln!("Skip unsafe entry: {:?}", name);
+                }
+                Ok(export_files
+                    .into_iter()
+                    .filter(|export_file| entry_path::is_safe(&export_file.internal_file_name))
+                    .collect())
+            },
+            PathPolicy::Strip => Ok(export_files
+                .into_iter()
+                .filter_map(|mut export_file| {
+                    let stripped_name = entry_path::strip(&export_file.internal_file_name);
+                    if stripped_name.is_empty() {
+                        eprintln!("Skip unsafe entry: {:?}", export_file.internal_file_name);
+                        return None;
+                    }
+                    export_file.file_path = entry_path::to_relative_path(&stripped_name);
+                    Some(export_file)
+                })
+                .collect())
+        }
+    }
+
+    fn prepare_extraction_directory(base_directory: &Path) -> Result<(), MegError> {
         if base_directory.is_dir() {
             Ok(())
         }
         else if !base_directory.exists() {
-            std::fs::create_dir_all(&base_directory)
+            Ok(std::fs::create_dir_all(base_directory)?)
         }
         else {
-            Err(std::io::Error::new(std::io::ErrorKind::InvalidInput,
-                                    "Invalid base directory. Must either be directory or non-existing directory (e.g not file)"))
+            Err(MegError::InvalidBaseDirectory(base_directory.to_path_buf()))
         }
     }
 
@@ -83,7 +366,7 @@ impl<'a> MegaFile
             .map(move |table_record| {
                 let internal_path = self.filename_table[table_record.name as usize].filename.clone();
                 ExportFile {
-                    file_path: PathBuf::from(&internal_path),
+                    file_path: entry_path::to_relative_path(&internal_path),
                     internal_file_name: internal_path,
                     table_record: table_record.clone()
                 }
@@ -97,84 +380,402 @@ impl<'a> MegaFile
     pub fn get_metadata_iterator(&'a self) -> impl Iterator<Item = FileMeta> + 'a {
         self.table_records
             .iter()
-            .map(move |table_record| FileMeta::create_from_table_record(&table_record,
+            .map(move |table_record| FileMeta::create_from_table_record(table_record,
                                                                         &self.filename_table))
     }
 
-    pub fn create_from_directory(input_dir: &PathBuf, output_file_path: &PathBuf) -> MegaFile {
-        let mut output_file = File::create(output_file_path).unwrap();
-        let files = MegaFile::get_files_to_zip_from_directory_sorted(input_dir);
+    pub fn create_from_directory(input_dir: &Path, output_file_path: &Path) -> Result<MegaFile, MegError> {
+        MegaFile::create_from_directory_with_options(input_dir, output_file_path,
+                                                     &CreateOptions::default())
+    }
+
+    pub fn create_from_directory_with_options(input_dir: &Path,
+                                              output_file_path: &Path,
+                                              options: &CreateOptions) -> Result<MegaFile, MegError> {
+        MegaFile::create_from_input(CreateInput::Directory(input_dir), output_file_path, options)
+    }
+
+    pub fn create_from_manifest(manifest_path: &Path,
+                                output_file_path: &Path,
+                                options: &CreateOptions) -> Result<MegaFile, MegError> {
+        MegaFile::create_from_input(CreateInput::Manifest(manifest_path), output_file_path, options)
+    }
+
+    pub fn create_from_tar_file(input_file: &Path,
+                                output_file_path: &Path,
+                                options: &CreateOptions) -> Result<MegaFile, MegError> {
+        MegaFile::create_from_input(CreateInput::TarFile(input_file), output_file_path, options)
+    }
+
+    pub fn create_from_tar_reader<R: Read>(reader: R,
+                                           output_file_path: &Path,
+                                           options: &CreateOptions) -> Result<MegaFile, MegError> {
+        let files = archive_input::entries_from_tar_reader(reader, &options.mount_prefix)?;
+        MegaFile::create_from_entries(MegaFile::sorted_files_by_path(files), output_file_path, options)
+    }
+
+    pub fn create_from_zip_file(input_file: &Path,
+                                output_file_path: &Path,
+                                options: &CreateOptions) -> Result<MegaFile, MegError> {
+        MegaFile::create_from_input(CreateInput::ZipFile(input_file), output_file_path, options)
+    }
+
+    pub fn create_from_input(input: CreateInput<'_>,
+                             output_file_path: &Path,
+                             options: &CreateOptions) -> Result<MegaFile, MegError> {
+        let (files, options) = MegaFile::collect_input_entries(input, options)?;
+        MegaFile::create_from_entries(files, output_file_path, &options)
+    }
+
+    // Builds the archive twice without storing it and fails unless both builds hash the same,
+    // which catches inputs that change while packing as well as nondeterminism in the writer.
+    pub fn check_reproducible(input: CreateInput<'_>, options: &CreateOptions) -> Result<ContentHash, MegError> {
+        let first = MegaFile::hash_input(input, options)?;
+        let second = MegaFile::hash_input(input, options)?;
+        if first != second {
+            return Err(MegError::NotReproducible { first: first.to_string(), second: second.to_string() });
+        }
+        Ok(first)
+    }
+
+    fn hash_input(input: CreateInput<'_>, options: &CreateOptions) -> Result<ContentHash, MegError> {
+        let (files, options) = MegaFile::collect_input_entries(input, options)?;
+        MegaFile::check_entries(&files, &options)?;
+
+        let mut hash_writer = HashWriter::new();
+        MegaFile::write_entries(files, &mut hash_writer, &options)?;
+        Ok(hash_writer.finish())
+    }
+
+    // Manifests may choose their own data order, so the options to build with are returned
+    // along with the entries.
+    fn collect_input_entries(input: CreateInput<'_>,
+                             options: &CreateOptions) -> Result<(Vec<PackEntry>, CreateOptions), MegError> {
+        let files = match input {
+            CreateInput::Directory(input_dir) =>
+                MegaFile::get_files_to_zip_from_directory_sorted(input_dir, &options.mount_prefix)?,
+            CreateInput::TarFile(input_file) =>
+                MegaFile::sorted_files_by_path(archive_input::entries_from_tar_file(input_file,
+                                                                                    &options.mount_prefix)?),
+            CreateInput::ZipFile(input_file) =>
+                MegaFile::sorted_files_by_path(archive_input::entries_from_zip_file(input_file,
+                                                                                    &options.mount_prefix)?),
+            CreateInput::Manifest(manifest_path) => {
+                let manifest = Manifest::from_file(manifest_path)?;
+                let base_directory = manifest_path.parent().unwrap_or_else(|| Path::new(""));
+                let files = manifest.get_pack_entries(base_directory, &options.mount_prefix)?;
+
+                let options = CreateOptions { data_order: manifest.data_order.unwrap_or(options.data_order),
+                                              ..options.clone() };
+                return Ok((files, options));
+            }
+        };
+        Ok((files, options.clone()))
+    }
+
+    pub fn convert_to(&self, output_file_path: &Path, options: &CreateOptions) -> Result<MegaFile, MegError> {
+        self.check_output_is_not_input(output_file_path)?;
+        MegaFile::create_from_entries(self.get_pack_entries()?, output_file_path, options)
+    }
+
+    // Entries are streamed from this archive while the output is written, so writing over
+    // it would destroy them before they are read.
+    pub fn is_stored_at(&self, path: &Path) -> bool {
+        osext::is_same_file(&self.path, path)
+    }
+
+    fn check_output_is_not_input(&self, output_file_path: &Path) -> Result<(), MegError> {
+        if self.is_stored_at(output_file_path) {
+            return Err(MegError::OutputIsInput(output_file_path.to_path_buf()));
+        }
+        Ok(())
+    }
+
+    // Writes a copy of the archive with entries added, replaced and removed. Entries that are
+    // kept are streamed from this archive, so the output must be a different file.
+    pub fn update_to(&self,
+                     output_file_path: &Path,
+                     update: &UpdateOptions,
+                     options: &CreateOptions) -> Result<MegaFile, MegError> {
+        self.check_output_is_not_input(output_file_path)?;
+        let missing_entries: Vec<String> = update.removals
+            .iter()
+            .filter(|name| self.find(name).is_none())
+            .cloned()
+            .collect();
+        if !missing_entries.is_empty() {
+            return Err(MegError::MissingEntries(missing_entries));
+        }
+
+        let dropped_names: Vec<String> = update.files
+            .iter()
+            .map(|(name, _path)| name)
+            .chain(update.removals.iter())
+            .map(|name| Filename::normalize(name))
+            .collect();
+        let mut files: Vec<PackEntry> = self.get_pack_entries()?
+            .into_iter()
+            .filter(|pack_entry| !dropped_names.contains(&pack_entry.internal_file_name))
+            .collect();
+        for (name, path) in &update.files {
+            files.push(PackEntry::from_path(path, name.clone())?);
+        }
+
+        MegaFile::create_from_entries(MegaFile::sorted_files_by_path(files), output_file_path, options)
+    }
+
+    // Combines the sources into one archive where later sources override entries of earlier
+    // ones, and reports every overridden entry. Like updates, the output cannot be one of the
+    // archives being merged.
+    pub fn merge(sources: &[MergeSource<'_>],
+                 output_file_path: &Path,
+                 options: &CreateOptions) -> Result<(MegaFile, Vec<Override>), MegError> {
+        for source in sources {
+            if let MergeSource::Archive(mega_file) = source {
+                mega_file.check_output_is_not_input(output_file_path)?;
+            }
+        }
+        let (files, overrides) = merge::collect_entries(sources, &options.mount_prefix)?;
+        Ok((MegaFile::create_from_entries(files, output_file_path, options)?, overrides))
+    }
+
+    // Entries are returned in filename table order, so converting an archive keeps its names
+    // in the same order.
+    fn get_pack_entries(&self) -> Result<Vec<PackEntry>, MegError> {
+        let mut table_records = self.table_records.clone();
+        table_records.sort_by_key(|table_record| table_record.name);
+
+        let file = Arc::new(self.file.try_clone()?);
+        Ok(table_records
+            .iter()
+            .map(|table_record| {
+                PackEntry::from_archive_entry(self.filename_table[table_record.name as usize].filename.clone(),
+                                              Arc::clone(&file),
+                                              table_record,
+                                              self.encryption.clone())
+            })
+            .collect())
+    }
+
+    pub fn create_from_entries(files: Vec<PackEntry>,
+                               output_file_path: &Path,
+                               options: &CreateOptions) -> Result<MegaFile, MegError> {
+        MegaFile::check_entries(&files, options)?;
+
+        let mut output_file = BufWriter::new(File::create(output_file_path)?);
+        let (header, filename_table, table_records) = MegaFile::write_entries(files, &mut output_file, options)?;
+        output_file.into_inner().map_err(|error| error.into_error())?;
+
+        // The returned archive reads entries back, which the write handle does not allow.
+        Ok(MegaFile {
+            file: File::open(output_file_path)?,
+            path: output_file_path.to_path_buf(),
+            header,
+            filename_table,
+            sorted_by_crc: MegaFile::is_sorted_by_crc(&table_records),
+            table_records,
+            encryption: options.encryption.clone()
+        })
+    }
+
+    fn check_entries(files: &[PackEntry], options: &CreateOptions) -> Result<(), MegError> {
+        if options.encryption.is_some() && options.version != MegVersion::V3 {
+            return Err(MegError::EncryptionNotSupported { version: options.version });
+        }
+        if options.version == MegVersion::V3 && files.len() > u16::MAX as usize + 1 {
+            return Err(MegError::TooManyEntries { version: options.version, count: files.len() });
+        }
+        MegaFile::check_duplicate_names(files)
+    }
+
+    // The output only depends on the entries, their order and the options, so the same
+    // input always gives the same bytes.
+    fn write_entries<W: Write>(files: Vec<PackEntry>,
+                               writer: &mut W,
+                               options: &CreateOptions) -> Result<(Header, Vec<Filename>, Vec<TableRecord>), MegError> {
+        let key = options.encryption.as_ref();
+
         let files = MegaFile::set_file_name_indices(files);
+        let files = MegaFile::set_encryption_flags(files, key.is_some());
+        let file_names = MegaFile::get_file_names(&files);
+        let filename_table = MegaFile::get_file_name_containers(&files);
 
-        let header_len = file_writer::write_header(&mut output_file, files.len(), files.len());
-        let filenames_len = file_writer::write_file_names(&mut output_file,
-                                                          &MegaFile::get_file_names(&files));
-        let table_records_size = MegaFile::compute_table_records_size(&files);
+        let header_len = Header::get_binary_size_for_version(options.version);
+        let filenames_len = match key {
+            Some(_) => encryption::padded_size(file_writer::compute_file_names_size(&file_names)),
+            None => file_writer::compute_file_names_size(&file_names)
+        };
+        let table_records_size = MegaFile::compute_table_records_size(&files, options.version,
+                                                                      key.is_some());
+        // The header points at the end of the tables, which is what readers expect, and any
+        // reserved space or padding lies between there and the first entry.
         let files_start_index = header_len + filenames_len + table_records_size;
 
+        let header = match options.version {
+            MegVersion::V1 => Header::create(file_names.len() as u32, files.len() as u32),
+            MegVersion::V2 => Header::create_v2(file_names.len() as u32, files.len() as u32,
+                                                files_start_index as u32),
+            MegVersion::V3 => Header::create_v3(file_names.len() as u32, files.len() as u32,
+                                                files_start_index as u32, filenames_len as u32,
+                                                key.is_some()),
+        };
+        file_writer::write_header(writer, &header)?;
+        match key {
+            Some(key) => file_writer::write_encrypted_file_names(writer, &file_names, key)?,
+            None => file_writer::write_file_names(writer, &file_names)?
+        };
+
         let files = MegaFile::order_files_by_crc(files);
-        let files = MegaFile::setup_table_records(files, files_start_index);
+        let content_hashes = if options.deduplicate {
+            MegaFile::hash_duplicate_candidates(&files)?
+        }
+        else {
+            vec![None; files.len()]
+        };
+        let reserved_end = files_start_index as u64 + options.reserved_table_space as u64;
+        let mut files = MegaFile::setup_table_records(files, &content_hashes, reserved_end, options)?;
 
-        let table_records = file_writer::write_file_table_records(&mut output_file, &files);
-        file_writer::write_files(&mut output_file, &files);
+        let table_records = file_writer::write_file_table_records(writer, &files, options.version, key)?;
+        // Entries sharing data have the same start, and only the first of them is written.
+        // Empty entries may share a start with the next entry, so larger entries go first.
+        files.sort_by_key(|pack_entry| (pack_entry.table_record.start, Reverse(pack_entry.table_record.size)));
+        files.dedup_by_key(|pack_entry| pack_entry.table_record.start);
+        file_writer::write_padding(writer, options.reserved_table_space as u64)?;
+        file_writer::write_files(writer, &files, reserved_end, key)?;
+        Ok((header, filename_table, table_records))
+    }
 
-        MegaFile {
-            file: output_file,
-            _header: Header::create(MegaFile::get_file_names(&files).len() as u32,
-                                    files.len() as u32),
-            filename_table: MegaFile::get_file_name_containers(&files),
-            table_records: table_records
-        }
+    fn get_files_to_zip_from_directory_sorted(input_dir: &Path,
+                                              mount_prefix: &str) -> Result<Vec<PackEntry>, MegError> {
+        let root = if input_dir.is_dir() { input_dir } else { input_dir.parent().unwrap_or_else(|| Path::new("")) };
+        let files_to_read = osext::list_files_recursive(input_dir)?;
+        Ok(MegaFile::sorted_files_by_path(files_to_read.iter()
+                                                       .map(|path| {
+                                                           let name = Filename::from_relative_path(path, root,
+                                                                                                   mount_prefix)?;
+                                                           PackEntry::from_path(path, name.filename)
+                                                       })
+                                                       .collect::<Result<Vec<PackEntry>, MegError>>()?))
     }
 
-    fn get_files_to_zip_from_directory_sorted(input_dir: &PathBuf) -> Vec<ExportFile> {
-        let files_to_read = osext::list_files_recursive(input_dir).unwrap();
-        MegaFile::sorted_files_by_path(files_to_read.iter()
-                                                    .map(|path| ExportFile::from_path(path))
-                                                    .collect::<Vec<ExportFile>>())
+    // Names are already uppercase with backslash separators, so comparing their bytes gives
+    // the same order whatever the listing order, locale or host separator.
+    fn sorted_files_by_path(mut file_list: Vec<PackEntry>) -> Vec<PackEntry> {
+        file_list.sort_by(|a, b| a.internal_file_name.as_bytes().cmp(b.internal_file_name.as_bytes()));
+        file_list
     }
 
-    fn sorted_files_by_path(mut file_list: Vec<ExportFile>) -> Vec<ExportFile> {
-        file_list.sort_by_key(|export_file| export_file.internal_file_name.clone() );
+    fn set_file_name_indices(mut file_list: Vec<PackEntry>) -> Vec<PackEntry> {
+        for (i, pack_entry) in file_list.iter_mut().enumerate() {
+            pack_entry.table_record.name = i as u32;
+        }
         file_list
     }
 
-    fn set_file_name_indices(mut file_list: Vec<ExportFile>) -> Vec<ExportFile> {
-        for (i, export_file) in file_list.iter_mut().enumerate() {
-            export_file.table_record.name = i as u32;
+    fn set_encryption_flags(mut file_list: Vec<PackEntry>, encrypted: bool) -> Vec<PackEntry> {
+        if encrypted {
+            for pack_entry in file_list.iter_mut() {
+                pack_entry.table_record.flags |= table_record::TABLE_RECORD_FLAG_ENCRYPTED;
+            }
         }
         file_list
     }
 
-    fn get_file_names(file_list: &Vec<ExportFile>) -> Vec<String> {
+    fn get_file_names(file_list: &[PackEntry]) -> Vec<String> {
         file_list.iter()
-                 .map(|export_file| export_file.internal_file_name.clone())
+                 .map(|pack_entry| pack_entry.internal_file_name.clone())
                  .collect()
     }
 
-    fn compute_table_records_size(file_list: &Vec<ExportFile>) -> usize {
-        file_list.iter()
-                 .map(|export_file| export_file.get_table_record().get_binary_size())
-                 .sum()
+    fn compute_table_records_size(file_list: &[PackEntry], version: MegVersion, encrypted: bool) -> usize {
+        let record_size = TableRecord::get_binary_size_for_version(version);
+        let record_size = if encrypted { encryption::padded_size(record_size) } else { record_size };
+        file_list.len() * record_size
     }
 
-    fn order_files_by_crc(mut file_list: Vec<ExportFile>) -> Vec<ExportFile> {
-        file_list.sort_by_key(|export_file| export_file.table_record.crc);
+    // Records with equal CRCs are ordered by name, which is unique, so the order never
+    // depends on the order the entries were given in.
+    fn order_files_by_crc(mut file_list: Vec<PackEntry>) -> Vec<PackEntry> {
+        file_list.sort_by(|a, b| a.table_record.crc.cmp(&b.table_record.crc)
+                                  .then_with(|| a.internal_file_name.as_bytes().cmp(b.internal_file_name.as_bytes())));
         file_list
     }
 
-    fn setup_table_records(mut file_list: Vec<ExportFile>, files_start_index: usize) -> Vec<ExportFile> {
-        let mut current_file_index = files_start_index;
-        for (i, export_file) in file_list.iter_mut().enumerate() {
-            export_file.table_record.index = i as u32;
-            export_file.table_record.start = current_file_index as u32;
-            current_file_index += export_file.table_record.size as usize;
+    // Only entries that have the same size as another entry can share data, so the content
+    // of the others is never read twice.
+    fn hash_duplicate_candidates(file_list: &[PackEntry]) -> Result<Vec<Option<ContentHash>>, MegError> {
+        let mut size_counts: HashMap<u32, usize> = HashMap::new();
+        for pack_entry in file_list {
+            *size_counts.entry(pack_entry.table_record.size).or_insert(0) += 1;
         }
+
         file_list
+            .iter()
+            .map(|pack_entry| {
+                let size = pack_entry.table_record.size;
+                if size == 0 || size_counts[&size] == 1 {
+                    return Ok(None);
+                }
+                Ok(Some(ContentHash::from_reader(&mut pack_entry.open()?.take(size as u64), size as u64)?))
+            })
+            .collect()
+    }
+
+    // Expects the entries in CRC order. With input data order the data is laid out by name
+    // index instead, which is the position the entry was given in. Entries with a content
+    // hash seen before point at the data of the first one. Empty entries are not aligned, so
+    // they never point past the end of the archive.
+    fn setup_table_records(mut file_list: Vec<PackEntry>,
+                           content_hashes: &[Option<ContentHash>],
+                           files_start_index: u64,
+                           options: &CreateOptions) -> Result<Vec<PackEntry>, MegError> {
+        let alignment = options.alignment.max(1) as u64;
+        let mut data_positions: Vec<usize> = (0..file_list.len()).collect();
+        if options.data_order == DataOrder::Input {
+            data_positions.sort_by_key(|&i| file_list[i].table_record.name);
+        }
+
+        let mut shared_starts: HashMap<ContentHash, u32> = HashMap::new();
+        let mut current_file_index = files_start_index;
+        for i in data_positions {
+            let pack_entry = &mut file_list[i];
+            pack_entry.table_record.index = i as u32;
+            if pack_entry.table_record.size > 0 {
+                current_file_index = current_file_index.div_ceil(alignment) * alignment;
+            }
+
+            if let Some(content_hash) = content_hashes[i] {
+                if let Some(&start) = shared_starts.get(&content_hash) {
+                    pack_entry.table_record.start = start;
+                    continue;
+                }
+                shared_starts.insert(content_hash, current_file_index as u32);
+            }
+            pack_entry.table_record.start = current_file_index as u32;
+            current_file_index += pack_entry.table_record.get_stored_size();
+        }
+        if current_file_index > u32::MAX as u64 + 1 {
+            return Err(MegError::ArchiveTooLarge { size: current_file_index });
+        }
+        Ok(file_list)
+    }
+
+    fn check_duplicate_names(file_list: &[PackEntry]) -> Result<(), MegError> {
+        let mut names: Vec<&String> = file_list.iter().map(|pack_entry| &pack_entry.internal_file_name).collect();
+        names.sort();
+        let mut duplicate_names: Vec<String> = names.windows(2)
+                                                    .filter(|pair| pair[0] == pair[1])
+                                                    .map(|pair| pair[0].clone())
+                                                    .collect();
+        duplicate_names.dedup();
+        if !duplicate_names.is_empty() {
+            return Err(MegError::DuplicateEntries(duplicate_names));
+        }
+        Ok(())
     }
 
-    fn get_file_name_containers(file_list: &Vec<ExportFile>) -> Vec<Filename> {
-        MegaFile::get_file_names(&file_list)
+    fn get_file_name_containers(file_list: &[PackEntry]) -> Vec<Filename> {
+        MegaFile::get_file_names(file_list)
             .iter()
             .map(|filename_str| Filename{ filename: filename_str.clone() } )
             .collect()
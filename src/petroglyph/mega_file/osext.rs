@@ -1,21 +1,23 @@
-use std::fs::File;
-use std::io::Read;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-pub fn list_files_recursive(dir: &PathBuf) -> Result<Vec<PathBuf>, std::io::Error> {
+pub fn list_files_recursive(dir: &Path) -> Result<Vec<PathBuf>, std::io::Error> {
     if dir.is_dir() {
         list_directory_files_recursive(dir)
     }
     else {
-        Ok(vec![dir.clone()])
+        Ok(vec![dir.to_path_buf()])
     }
 }
 
-fn list_directory_files_recursive(dir: &PathBuf) -> Result<Vec<PathBuf>, std::io::Error> {
+// Entries are visited in path order, as read_dir order differs between file systems.
+fn list_directory_files_recursive(dir: &Path) -> Result<Vec<PathBuf>, std::io::Error> {
     let mut directory_files = Vec::new();
 
-    for entry in dir.read_dir()? {
-        let path = entry?.path();
+    let mut paths = dir.read_dir()?
+                       .map(|entry| entry.map(|entry| entry.path()))
+                       .collect::<Result<Vec<PathBuf>, std::io::Error>>()?;
+    paths.sort();
+    for path in paths {
         let substructure_files = list_files_recursive(&path)?;
         directory_files.extend(substructure_files);
     }
@@ -23,10 +25,50 @@ fn list_directory_files_recursive(dir: &PathBuf) -> Result<Vec<PathBuf>, std::io
     Ok(directory_files)
 }
 
-pub fn get_file_size(path: &PathBuf) -> Result<usize, std::io::Error> {
-    let mut file = File::open(path)?;
-    let mut file_content = Vec::new();
-    file.read_to_end(&mut file_content)?;
+pub fn get_file_size(path: &Path) -> Result<u64, std::io::Error> {
+    Ok(std::fs::metadata(path)?.len())
+}
+
+// Windows paths are case insensitive, so a component that does not exist as written is
+// matched against the directory entries ignoring ASCII case.
+pub fn find_case_insensitive(root: &Path, relative_path: &Path) -> Option<PathBuf> {
+    let mut resolved = root.to_path_buf();
+    for component in relative_path.iter() {
+        let exact = resolved.join(component);
+        if exact.exists() {
+            resolved = exact;
+            continue;
+        }
+
+        let component = component.to_str()?;
+        let entry = resolved.read_dir().ok()?
+            .filter_map(|entry| entry.ok())
+            .find(|entry| entry.file_name().to_str().is_some_and(|name| name.eq_ignore_ascii_case(component)))?;
+        resolved = entry.path();
+    }
+    Some(resolved)
+}
+
+// Two paths name the same file when they resolve to the same canonical path, or on Unix
+// when they are hard links to the same inode. Paths that do not exist are never the same.
+pub fn is_same_file(first: &Path, second: &Path) -> bool {
+    match (first.canonicalize(), second.canonicalize()) {
+        (Ok(first), Ok(second)) if first == second => true,
+        (Ok(first), Ok(second)) => is_same_inode(&first, &second),
+        _ => false
+    }
+}
+
+#[cfg(unix)]
+fn is_same_inode(first: &Path, second: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    match (std::fs::metadata(first), std::fs::metadata(second)) {
+        (Ok(first), Ok(second)) => first.dev() == second.dev() && first.ino() == second.ino(),
+        _ => false
+    }
+}
 
-    Ok(file_content.len())
+#[cfg(not(unix))]
+fn is_same_inode(_first: &Path, _second: &Path) -> bool {
+    false
 }
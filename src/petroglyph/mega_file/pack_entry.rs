@@ -0,0 +1,133 @@
+use super::TableRecord;
+use super::Filename;
+use super::EncryptionKey;
+use super::crc;
+use super::osext;
+use super::entry_reader::EntryReader;
+use super::error::MegError;
+
+use std::fs::File;
+use flate2::read::DeflateDecoder;
+
+use std::io::{Cursor, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+pub enum EntrySource
+{
+    File(PathBuf),
+    Archive {
+        file: Arc<File>,
+        table_record: TableRecord,
+        key: Option<EncryptionKey>
+    },
+    Range {
+        file: Arc<File>,
+        start: u64,
+        size: u64
+    },
+    Deflated {
+        file: Arc<File>,
+        start: u64,
+        compressed_size: u64
+    },
+    Memory(Arc<[u8]>)
+}
+
+pub struct PackEntry
+{
+    pub internal_file_name: String,
+    pub source: EntrySource,
+    pub table_record: TableRecord
+}
+
+impl PackEntry
+{
+    pub fn from_path(path: &Path, internal_file_name: String) -> Result<PackEntry, MegError> {
+        let size = osext::get_file_size(path)?;
+        if size > u32::MAX as u64 {
+            return Err(MegError::EntryTooLarge { path: path.to_path_buf(), size });
+        }
+        Ok(PackEntry::create(internal_file_name, EntrySource::File(path.to_path_buf()), size as u32))
+    }
+
+    pub fn from_archive_entry(internal_file_name: String,
+                              file: Arc<File>,
+                              table_record: &TableRecord,
+                              key: Option<EncryptionKey>) -> PackEntry {
+        let size = table_record.size;
+        let source = EntrySource::Archive { file, table_record: table_record.clone(), key };
+        PackEntry::create(internal_file_name, source, size)
+    }
+
+    // A member stored uncompressed inside another archive file, like a tar or stored zip member.
+    pub fn from_range(internal_file_name: String,
+                      file: Arc<File>,
+                      start: u64,
+                      size: u64) -> Result<PackEntry, MegError> {
+        let entry_size = PackEntry::check_size(&internal_file_name, size)?;
+        Ok(PackEntry::create(internal_file_name, EntrySource::Range { file, start, size }, entry_size))
+    }
+
+    pub fn from_deflated(internal_file_name: String,
+                         file: Arc<File>,
+                         start: u64,
+                         compressed_size: u64,
+                         size: u64) -> Result<PackEntry, MegError> {
+        let entry_size = PackEntry::check_size(&internal_file_name, size)?;
+        let source = EntrySource::Deflated { file, start, compressed_size };
+        Ok(PackEntry::create(internal_file_name, source, entry_size))
+    }
+
+    pub fn from_bytes(internal_file_name: String, content: Vec<u8>) -> Result<PackEntry, MegError> {
+        let size = PackEntry::check_size(&internal_file_name, content.len() as u64)?;
+        Ok(PackEntry::create(internal_file_name, EntrySource::Memory(content.into()), size))
+    }
+
+    fn check_size(internal_file_name: &str, size: u64) -> Result<u32, MegError> {
+        if size > u32::MAX as u64 {
+            return Err(MegError::EntryTooLarge { path: PathBuf::from(internal_file_name), size });
+        }
+        Ok(size as u32)
+    }
+
+    // The game hashes the normalized name, so every entry is stored under that name too.
+    fn create(internal_file_name: String, source: EntrySource, size: u32) -> PackEntry {
+        let internal_file_name = Filename::normalize(&internal_file_name);
+        PackEntry {
+            table_record: TableRecord{
+                flags: 0,
+                crc: crc::crc32::compute_from_bytes(internal_file_name.as_bytes()),
+                index: 0,
+                size,
+                start: 0,
+                name: 0
+            },
+            internal_file_name,
+            source
+        }
+    }
+
+    pub fn open(&self) -> Result<Box<dyn Read>, MegError> {
+        match &self.source {
+            EntrySource::File(path) => Ok(Box::new(File::open(path)?)),
+            EntrySource::Archive { file, table_record, key } =>
+                Ok(Box::new(EntryReader::create(file.try_clone()?, table_record, key.as_ref())?)),
+            EntrySource::Range { file, start, size } => {
+                let mut file = file.try_clone()?;
+                file.seek(SeekFrom::Start(*start))?;
+                Ok(Box::new(file.take(*size)))
+            },
+            EntrySource::Deflated { file, start, compressed_size } => {
+                let mut file = file.try_clone()?;
+                file.seek(SeekFrom::Start(*start))?;
+                Ok(Box::new(DeflateDecoder::new(file.take(*compressed_size))))
+            },
+            EntrySource::Memory(content) => Ok(Box::new(Cursor::new(Arc::clone(content))))
+        }
+    }
+
+    pub fn get_table_record(&self) -> &TableRecord {
+        &self.table_record
+    }
+}
@@ -1,21 +1,33 @@
 use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use serde::{Serialize, Serializer};
 
 use std::io::Write;
 use std::io::Seek;
 use std::io::Read;
 
+use super::MegVersion;
+use super::encryption;
+use super::error::{self, MegError};
+
+pub const TABLE_RECORD_FLAG_ENCRYPTED: u16 = 0x0001;
+
+#[derive(Serialize)]
 pub struct TableRecord
 {
+    pub flags: u16,
+    #[serde(serialize_with = "serialize_hex")]
     pub crc: u32,
     pub index: u32,
     pub size: u32,
     pub start: u32,
+    #[serde(rename = "name_index")]
     pub name: u32
 }
 
 impl Clone for TableRecord {
     fn clone(&self) -> TableRecord {
         TableRecord {
+            flags: self.flags,
             crc: self.crc,
             index: self.index,
             size: self.size,
@@ -27,26 +39,75 @@ impl Clone for TableRecord {
 
 impl TableRecord
 {
-    pub fn create_from_cursor<R: Read + Seek>(reader: &mut R) -> Result<TableRecord,
-                                                                        std::io::Error> {
-        let crc = reader.read_u32::<LittleEndian>()?;
-        let index = reader.read_u32::<LittleEndian>()?;
-        let size = reader.read_u32::<LittleEndian>()?;
-        let start = reader.read_u32::<LittleEndian>()?;
-        let name = reader.read_u32::<LittleEndian>()?;
+    pub fn create_from_cursor<R: Read + Seek>(reader: &mut R) -> Result<TableRecord, MegError> {
+        let crc = reader.read_u32::<LittleEndian>().map_err(truncated_file_table)?;
+        let index = reader.read_u32::<LittleEndian>().map_err(truncated_file_table)?;
+        let size = reader.read_u32::<LittleEndian>().map_err(truncated_file_table)?;
+        let start = reader.read_u32::<LittleEndian>().map_err(truncated_file_table)?;
+        let name = reader.read_u32::<LittleEndian>().map_err(truncated_file_table)?;
+
+        Ok( TableRecord{ flags: 0, crc, index, size, start, name } )
+    }
+
+    pub fn create_v3_from_cursor<R: Read + Seek>(reader: &mut R) -> Result<TableRecord, MegError> {
+        let flags = reader.read_u16::<LittleEndian>().map_err(truncated_file_table)?;
+        let crc = reader.read_u32::<LittleEndian>().map_err(truncated_file_table)?;
+        let index = reader.read_u32::<LittleEndian>().map_err(truncated_file_table)?;
+        let size = reader.read_u32::<LittleEndian>().map_err(truncated_file_table)?;
+        let start = reader.read_u32::<LittleEndian>().map_err(truncated_file_table)?;
+        let name = reader.read_u16::<LittleEndian>().map_err(truncated_file_table)? as u32;
+
+        Ok( TableRecord{ flags, crc, index, size, start, name } )
+    }
+
+    pub fn serialize<W: Write>(&self, writer: &mut W) -> Result<(), MegError> {
+        writer.write_u32::<LittleEndian>(self.crc)?;
+        writer.write_u32::<LittleEndian>(self.index)?;
+        writer.write_u32::<LittleEndian>(self.size)?;
+        writer.write_u32::<LittleEndian>(self.start)?;
+        writer.write_u32::<LittleEndian>(self.name)?;
+
+        Ok(())
+    }
 
-        Ok( TableRecord{ crc, index, size, start, name } )
+    pub fn serialize_v3<W: Write>(&self, writer: &mut W) -> Result<(), MegError> {
+        writer.write_u16::<LittleEndian>(self.flags)?;
+        writer.write_u32::<LittleEndian>(self.crc)?;
+        writer.write_u32::<LittleEndian>(self.index)?;
+        writer.write_u32::<LittleEndian>(self.size)?;
+        writer.write_u32::<LittleEndian>(self.start)?;
+        writer.write_u16::<LittleEndian>(self.name as u16)?;
+
+        Ok(())
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        self.flags & TABLE_RECORD_FLAG_ENCRYPTED != 0
     }
 
-    pub fn serialize<W: Write>(&self, writer: &mut W) {
-        writer.write_u32::<LittleEndian>(self.crc).unwrap();
-        writer.write_u32::<LittleEndian>(self.index).unwrap();
-        writer.write_u32::<LittleEndian>(self.size).unwrap();
-        writer.write_u32::<LittleEndian>(self.start).unwrap();
-        writer.write_u32::<LittleEndian>(self.name).unwrap();
+    // Encrypted entries take up their size rounded up to whole AES blocks.
+    pub fn get_stored_size(&self) -> u64 {
+        if self.is_encrypted() {
+            encryption::padded_size(self.size as usize) as u64
+        }
+        else {
+            self.size as u64
+        }
     }
 
-    pub fn get_binary_size(&self) -> usize {
-        std::mem::size_of::<u32>() * 5
+    pub fn get_binary_size_for_version(version: MegVersion) -> usize {
+        match version {
+            MegVersion::V1 | MegVersion::V2 => std::mem::size_of::<u32>() * 5,
+            MegVersion::V3 => std::mem::size_of::<u16>() * 2 + std::mem::size_of::<u32>() * 4,
+        }
     }
 }
+
+fn truncated_file_table(error: std::io::Error) -> MegError {
+    error::map_eof(error, MegError::TruncatedTable { table: "file" })
+}
+
+// CRCs are easier to compare with other tools as hex strings than as decimal numbers.
+fn serialize_hex<S: Serializer>(value: &u32, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&format!("{:#010X}", value))
+}
@@ -0,0 +1,30 @@
+use super::{EncryptionKey, EntryReader, ExportFile, MegError};
+
+use std::fs::File;
+use std::io::Write;
+
+// Entries become regular files named by their path components joined with slashes. The
+// modification time is left at zero so exporting the same archive always gives the same tar.
+pub fn write_tar<W: Write>(writer: W,
+                           export_files: &[ExportFile],
+                           file: &File,
+                           key: Option<&EncryptionKey>) -> Result<W, MegError> {
+    let mut builder = tar::Builder::new(writer);
+    for export_file in export_files {
+        let tar_path = export_file.file_path
+                                  .iter()
+                                  .map(|component| component.to_string_lossy())
+                                  .collect::<Vec<_>>()
+                                  .join("/");
+
+        let mut header = tar::Header::new_ustar();
+        header.set_size(export_file.table_record.size as u64);
+        header.set_mode(0o644);
+        header.set_mtime(0);
+        header.set_entry_type(tar::EntryType::Regular);
+
+        let entry_reader = EntryReader::create(file.try_clone()?, &export_file.table_record, key)?;
+        builder.append_data(&mut header, tar_path, entry_reader)?;
+    }
+    Ok(builder.into_inner()?)
+}
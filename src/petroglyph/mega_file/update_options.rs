@@ -0,0 +1,10 @@
+use std::path::PathBuf;
+
+// Files are (internal name, source path) pairs that are added, or replace the entry with
+// the same normalized name. Removals name entries that must exist in the archive.
+#[derive(Debug, Clone, Default)]
+pub struct UpdateOptions
+{
+    pub files: Vec<(String, PathBuf)>,
+    pub removals: Vec<String>,
+}
@@ -0,0 +1,164 @@
+use super::{crc, encryption};
+use super::{Filename, Header, MegVersion, TableRecord};
+
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Finding
+{
+    HeaderCountMismatch { num_filenames: u32, num_files: u32 },
+    TablesExceedFile { tables_end: u64, file_size: u64 },
+    DataStartMismatch { data_start: u32, tables_end: u64 },
+    NameIndexOutOfBounds { record: usize, name: u32 },
+    RecordOutOfBounds { record: usize, start: u32, size: u64, file_size: u64 },
+    RecordOverlapsTables { record: usize, start: u32, tables_end: u64 },
+    NotSortedByCrc { record: usize, crc: u32, previous_crc: u32 },
+    CrcMismatch { record: usize, stored: u32, computed: u32 },
+    IndexMismatch { record: usize, index: u32 },
+    OverlappingData { first: usize, second: usize },
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Finding::HeaderCountMismatch { num_filenames, num_files } =>
+                write!(f, "Header lists {} filenames but {} files", num_filenames, num_files),
+            Finding::TablesExceedFile { tables_end, file_size } =>
+                write!(f, "Header and tables need {} bytes but the archive is {} bytes", tables_end, file_size),
+            Finding::DataStartMismatch { data_start, tables_end } =>
+                write!(f, "Header puts the data at offset {} but the tables end at {}", data_start, tables_end),
+            Finding::NameIndexOutOfBounds { record, name } =>
+                write!(f, "Record {} refers to filename {} which does not exist", record, name),
+            Finding::RecordOutOfBounds { record, start, size, file_size } =>
+                write!(f, "Record {} ({} bytes at offset {}) lies outside the {} byte archive",
+                       record, size, start, file_size),
+            Finding::RecordOverlapsTables { record, start, tables_end } =>
+                write!(f, "Record {} starts at offset {}, inside the tables ending at {}", record, start, tables_end),
+            Finding::NotSortedByCrc { record, crc, previous_crc } =>
+                write!(f, "Record {} has crc {:#010X} which sorts before the previous crc {:#010X}",
+                       record, crc, previous_crc),
+            Finding::CrcMismatch { record, stored, computed } =>
+                write!(f, "Record {} stores crc {:#010X} but its filename hashes to {:#010X}",
+                       record, stored, computed),
+            Finding::IndexMismatch { record, index } =>
+                write!(f, "Record {} has index {}", record, index),
+            Finding::OverlappingData { first, second } =>
+                write!(f, "Data of records {} and {} overlap", first, second),
+        }
+    }
+}
+
+// Runs every check over already read tables and collects all findings rather than
+// stopping at the first one, so a single run describes everything wrong with an archive.
+pub fn verify(header: &Header,
+              filename_table: &[Filename],
+              table_records: &[TableRecord],
+              file_size: u64) -> Vec<Finding> {
+    let tables_end = compute_tables_end(header, filename_table);
+
+    let mut findings = verify_header(header, tables_end, file_size);
+    for (i, table_record) in table_records.iter().enumerate() {
+        findings.extend(verify_table_record(i, table_record, filename_table, tables_end, file_size));
+    }
+    findings.extend(verify_crc_order(table_records));
+    findings.extend(verify_data_ranges(table_records));
+    findings
+}
+
+pub fn compute_tables_end(header: &Header, filename_table: &[Filename]) -> u64 {
+    let filename_table_size = match header.version {
+        MegVersion::V3 => header.filename_table_size as u64,
+        _ => filename_table.iter()
+                           .map(|filename| (std::mem::size_of::<u16>() + filename.filename.len()) as u64)
+                           .sum()
+    };
+    let record_size = TableRecord::get_binary_size_for_version(header.version);
+    let record_size = if header.encrypted { encryption::padded_size(record_size) } else { record_size };
+
+    header.get_binary_size() as u64 + filename_table_size + header.num_files as u64 * record_size as u64
+}
+
+fn verify_header(header: &Header, tables_end: u64, file_size: u64) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    if header.num_filenames != header.num_files {
+        findings.push(Finding::HeaderCountMismatch { num_filenames: header.num_filenames,
+                                                     num_files: header.num_files });
+    }
+    if tables_end > file_size {
+        findings.push(Finding::TablesExceedFile { tables_end, file_size });
+    }
+    if header.version != MegVersion::V1 && header.data_start as u64 != tables_end {
+        findings.push(Finding::DataStartMismatch { data_start: header.data_start, tables_end });
+    }
+    findings
+}
+
+fn verify_table_record(record: usize,
+                       table_record: &TableRecord,
+                       filename_table: &[Filename],
+                       tables_end: u64,
+                       file_size: u64) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    match filename_table.get(table_record.name as usize) {
+        Some(filename) => {
+            let computed = crc::crc32::compute_from_bytes(filename.filename.as_bytes());
+            if computed != table_record.crc {
+                findings.push(Finding::CrcMismatch { record, stored: table_record.crc, computed });
+            }
+        },
+        None => findings.push(Finding::NameIndexOutOfBounds { record, name: table_record.name })
+    }
+
+    let size = table_record.get_stored_size();
+    if table_record.start as u64 + size > file_size {
+        findings.push(Finding::RecordOutOfBounds { record, start: table_record.start, size, file_size });
+    }
+    if size > 0 && (table_record.start as u64) < tables_end {
+        findings.push(Finding::RecordOverlapsTables { record, start: table_record.start, tables_end });
+    }
+    if table_record.index as usize != record {
+        findings.push(Finding::IndexMismatch { record, index: table_record.index });
+    }
+    findings
+}
+
+fn verify_crc_order(table_records: &[TableRecord]) -> Vec<Finding> {
+    table_records
+        .windows(2)
+        .enumerate()
+        .filter(|(_i, pair)| pair[1].crc < pair[0].crc)
+        .map(|(i, pair)| Finding::NotSortedByCrc { record: i + 1, crc: pair[1].crc, previous_crc: pair[0].crc })
+        .collect()
+}
+
+// Sorts the non-empty ranges by start and compares each one against the range reaching
+// furthest among those before it. Deduplicated archives point entries with the same content
+// at the same data, so a range identical to that one is not an overlap.
+fn verify_data_ranges(table_records: &[TableRecord]) -> Vec<Finding> {
+    let mut ranges: Vec<(u64, u64, usize)> = table_records
+        .iter()
+        .enumerate()
+        .filter(|(_i, table_record)| table_record.get_stored_size() > 0)
+        .map(|(i, table_record)| {
+            (table_record.start as u64, table_record.start as u64 + table_record.get_stored_size(), i)
+        })
+        .collect();
+    ranges.sort();
+
+    let mut findings = Vec::new();
+    let mut furthest: Option<(u64, u64, usize)> = None;
+    for (start, end, record) in ranges {
+        match furthest {
+            Some((furthest_start, furthest_end, _)) if start == furthest_start && end == furthest_end => {},
+            Some((_, furthest_end, furthest_record)) if start < furthest_end => {
+                findings.push(Finding::OverlappingData { first: furthest_record.min(record),
+                                                         second: furthest_record.max(record) });
+                if end > furthest_end {
+                    furthest = Some((start, end, record));
+                }
+            },
+            _ => furthest = Some((start, end, record))
+        }
+    }
+    findings
+}
@@ -0,0 +1,151 @@
+use super::{entry_path, osext};
+use super::{EncryptionKey, FileMeta, Filename, MegaFile, MegError};
+
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+pub enum VfsSource<'a>
+{
+    LooseFile(PathBuf),
+    Archive { path: &'a Path, mega_file: &'a MegaFile, meta: FileMeta },
+}
+
+impl std::fmt::Display for VfsSource<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VfsSource::LooseFile(path) => write!(f, "loose file {}", path.display()),
+            VfsSource::Archive { path, meta, .. } =>
+                write!(f, "{} in {}", meta.internal_file_name.filename, path.display()),
+        }
+    }
+}
+
+// Resolves paths the way the game does. Archives are loaded in the order MegaFiles.xml
+// lists them and later archives replace entries of earlier ones, which is how patch
+// archives listed last take effect. Loose files override every archive.
+pub struct Vfs
+{
+    archives: Vec<(PathBuf, MegaFile)>,
+    loose_directory: Option<PathBuf>,
+}
+
+impl Vfs
+{
+    // Archive paths in MegaFiles.xml are relative to the game directory, like Data\Config.meg.
+    pub fn create(mega_files_xml: &Path,
+                  game_directory: &Path,
+                  loose_directory: Option<PathBuf>,
+                  key: Option<EncryptionKey>) -> Result<Vfs, MegError> {
+        let archive_names = parse_mega_files_xml(&std::fs::read(mega_files_xml)?)?;
+
+        let mut archives = Vec::new();
+        for archive_name in archive_names {
+            let relative_path = entry_path::to_relative_path(&archive_name);
+            let archive_path = osext::find_case_insensitive(game_directory, &relative_path)
+                .ok_or_else(|| MegError::MissingArchive(game_directory.join(&relative_path)))?;
+            let mega_file = match key.clone() {
+                Some(key) => MegaFile::create_with_key(&archive_path, key)?,
+                None => MegaFile::create(&archive_path)?
+            };
+            archives.push((archive_path, mega_file));
+        }
+
+        Ok(Vfs{ archives, loose_directory })
+    }
+
+    pub fn resolve(&self, path: &str) -> Option<VfsSource<'_>> {
+        self.resolve_all(path).into_iter().next()
+    }
+
+    // Every source holding the path, starting with the one that wins.
+    pub fn resolve_all(&self, path: &str) -> Vec<VfsSource<'_>> {
+        let loose_file = self.loose_directory
+            .as_ref()
+            .filter(|_| entry_path::is_safe(path))
+            .and_then(|loose_directory| {
+                osext::find_case_insensitive(loose_directory, &entry_path::to_relative_path(path))
+            })
+            .filter(|loose_path| loose_path.is_file())
+            .map(VfsSource::LooseFile);
+
+        let archive_entries = self.archives
+            .iter()
+            .rev()
+            .filter_map(|(archive_path, mega_file)| {
+                mega_file.find(path).map(|meta| VfsSource::Archive { path: archive_path, mega_file, meta })
+            });
+
+        loose_file.into_iter().chain(archive_entries).collect()
+    }
+
+    pub fn exists(&self, path: &str) -> bool {
+        self.resolve(path).is_some()
+    }
+
+    pub fn open(&self, path: &str) -> Result<Box<dyn Read + '_>, MegError> {
+        match self.resolve(path) {
+            Some(VfsSource::LooseFile(loose_path)) => Ok(Box::new(File::open(loose_path)?)),
+            Some(VfsSource::Archive { mega_file, meta, .. }) => Ok(Box::new(mega_file.open_entry(&meta.table_record)?)),
+            None => Err(MegError::MissingEntries(vec![path.to_string()]))
+        }
+    }
+
+    // Normalized names of every path starting with the prefix, across all sources.
+    pub fn list(&self, prefix: &str) -> Result<Vec<String>, MegError> {
+        let prefix = Filename::normalize(prefix);
+        let mut names: Vec<String> = self.archives
+            .iter()
+            .flat_map(|(_path, mega_file)| mega_file.get_file_name_iterator())
+            .map(|name| Filename::normalize(name))
+            .collect();
+        // A missing loose directory holds no files, rather than being listed as one.
+        if let Some(loose_directory) = self.loose_directory.as_ref().filter(|directory| directory.is_dir()) {
+            for loose_path in osext::list_files_recursive(loose_directory)? {
+                names.push(Filename::from_relative_path(&loose_path, loose_directory, "")?.filename);
+            }
+        }
+
+        names.retain(|name| name.starts_with(&prefix));
+        names.sort();
+        names.dedup();
+        Ok(names)
+    }
+}
+
+// MegaFiles.xml is a flat list of <File> elements below a <Mega_Files> root, so the
+// archive names are read from the elements without a full XML parser.
+fn parse_mega_files_xml(content: &[u8]) -> Result<Vec<String>, MegError> {
+    let content = String::from_utf8_lossy(content);
+    let content = strip_comments(content.trim_start_matches('\u{feff}'));
+    let lowercase_content = content.to_ascii_lowercase();
+
+    let mut archive_names = Vec::new();
+    let mut position = 0;
+    while let Some(open_tag) = lowercase_content[position..].find("<file>") {
+        let name_start = position + open_tag + "<file>".len();
+        let name_length = lowercase_content[name_start..]
+            .find("</file>")
+            .ok_or_else(|| MegError::InvalidMegaFilesXml(format!("<File> at byte {} is not closed", name_start)))?;
+        let archive_name = content[name_start..name_start + name_length].trim();
+        if !archive_name.is_empty() {
+            archive_names.push(archive_name.to_string());
+        }
+        position = name_start + name_length;
+    }
+    Ok(archive_names)
+}
+
+fn strip_comments(content: &str) -> String {
+    let mut stripped = String::with_capacity(content.len());
+    let mut remaining = content;
+    while let Some(comment_start) = remaining.find("<!--") {
+        stripped.push_str(&remaining[..comment_start]);
+        remaining = match remaining[comment_start..].find("-->") {
+            Some(comment_length) => &remaining[comment_start + comment_length + "-->".len()..],
+            None => ""
+        };
+    }
+    stripped.push_str(remaining);
+    stripped
+}
@@ -0,0 +1,51 @@
+mod common;
+
+use std::fs;
+
+use meg_file_creator::{CreateOptions, MegaFile, MegVersion};
+
+// Reserved space and padding must not keep the version from being detected, so the
+// archives are reopened without giving it.
+#[test]
+fn aligns_entries_and_reserves_table_space() {
+    let (temp_dir, input_dir) = common::create_fixture_directory();
+    let all_options = vec![CreateOptions::default(),
+                           CreateOptions { version: MegVersion::V3, ..CreateOptions::default() },
+                           common::encrypted_options()];
+    for (i, options) in all_options.into_iter().enumerate() {
+        let archive_path = temp_dir.path().join(format!("archive{}.meg", i));
+        let options = CreateOptions { alignment: 4096, reserved_table_space: 1000, ..options };
+        MegaFile::create_from_directory_with_options(&input_dir, &archive_path, &options).unwrap();
+
+        let mega_file = match &options.encryption {
+            Some(key) => MegaFile::create_with_key(&archive_path, key.clone()).unwrap(),
+            None => MegaFile::create(&archive_path).unwrap()
+        };
+        assert_eq!(mega_file.get_version(), options.version);
+        assert_eq!(mega_file.verify().unwrap(), vec![]);
+
+        let content = fs::read(&archive_path).unwrap();
+        let data_start = mega_file.get_data_start() as usize;
+        let mut starts: Vec<usize> = mega_file.get_metadata_iterator()
+                                              .filter(|meta| meta.table_record.size > 0)
+                                              .map(|meta| meta.table_record.start as usize)
+                                              .collect();
+        starts.sort();
+        assert!(starts.iter().all(|start| start % 4096 == 0));
+        assert!(starts[0] >= data_start + 1000);
+        assert!(content[data_start..starts[0]].iter().all(|&byte| byte == 0));
+
+        common::assert_extracts_fixture(&mega_file, &temp_dir.path().join(format!("output{}", i)));
+    }
+}
+
+#[test]
+fn reserved_space_is_written_without_data() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let archive_path = temp_dir.path().join("archive.meg");
+    let options = CreateOptions { version: MegVersion::V2, reserved_table_space: 512, ..CreateOptions::default() };
+    let mega_file = MegaFile::create_from_entries(Vec::new(), &archive_path, &options).unwrap();
+
+    assert_eq!(fs::metadata(&archive_path).unwrap().len(), mega_file.get_data_start() + 512);
+    assert_eq!(MegaFile::create(&archive_path).unwrap().get_version(), MegVersion::V2);
+}
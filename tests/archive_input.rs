@@ -0,0 +1,130 @@
+mod common;
+
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+use meg_file_creator::{CreateInput, CreateOptions, MegaFile, MegError};
+
+fn fixture_members() -> Vec<(String, Vec<u8>)> {
+    let mut members: Vec<(String, Vec<u8>)> = common::FIXTURE_FILES
+        .iter()
+        .map(|(name, content)| (name.to_string(), content.to_vec()))
+        .collect();
+    members.push(("Data/Audio/music.bin".to_string(), common::binary_fixture_content()));
+    members
+}
+
+fn write_tar(path: &Path, members: &[(String, Vec<u8>)]) {
+    let mut builder = tar::Builder::new(File::create(path).unwrap());
+    for (name, content) in members {
+        let mut header = tar::Header::new_ustar();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        builder.append_data(&mut header, name, &content[..]).unwrap();
+    }
+    builder.finish().unwrap();
+}
+
+fn write_tar_gz(path: &Path, members: &[(String, Vec<u8>)]) {
+    let tar_path = path.with_extension("plain");
+    write_tar(&tar_path, members);
+    let mut encoder = flate2::write::GzEncoder::new(File::create(path).unwrap(), flate2::Compression::default());
+    encoder.write_all(&std::fs::read(&tar_path).unwrap()).unwrap();
+    encoder.finish().unwrap();
+}
+
+// Alternates stored and deflated members so both ways of reading zip members are covered.
+fn write_zip(path: &Path, members: &[(String, Vec<u8>)]) {
+    let mut writer = zip::ZipWriter::new(File::create(path).unwrap());
+    writer.add_directory("Data/", zip::write::SimpleFileOptions::default()).unwrap();
+    for (i, (name, content)) in members.iter().enumerate() {
+        let method = if i % 2 == 0 { zip::CompressionMethod::Stored } else { zip::CompressionMethod::Deflated };
+        let options = zip::write::SimpleFileOptions::default().compression_method(method);
+        writer.start_file(name, options).unwrap();
+        writer.write_all(content).unwrap();
+    }
+    writer.finish().unwrap();
+}
+
+#[test]
+fn tar_and_zip_inputs_match_directory_input() {
+    let (temp_dir, input_dir) = common::create_fixture_directory();
+    let options = CreateOptions { mount_prefix: "Game".to_string(), ..CreateOptions::default() };
+    let directory_archive = temp_dir.path().join("directory.meg");
+    MegaFile::create_from_directory_with_options(&input_dir, &directory_archive, &options).unwrap();
+
+    let tar_path = temp_dir.path().join("input.tar");
+    write_tar(&tar_path, &fixture_members());
+    let tar_archive = temp_dir.path().join("tar.meg");
+    MegaFile::create_from_tar_file(&tar_path, &tar_archive, &options).unwrap();
+
+    let stream_archive = temp_dir.path().join("stream.meg");
+    MegaFile::create_from_tar_reader(File::open(&tar_path).unwrap(), &stream_archive, &options).unwrap();
+
+    let zip_path = temp_dir.path().join("input.zip");
+    write_zip(&zip_path, &fixture_members());
+    let zip_archive = temp_dir.path().join("zip.meg");
+    MegaFile::create_from_zip_file(&zip_path, &zip_archive, &options).unwrap();
+
+    let expected = std::fs::read(&directory_archive).unwrap();
+    assert_eq!(std::fs::read(&tar_archive).unwrap(), expected);
+    assert_eq!(std::fs::read(&stream_archive).unwrap(), expected);
+    assert_eq!(std::fs::read(&zip_archive).unwrap(), expected);
+}
+
+// Members share one handle, so tarballs and zip files with more members than a process may
+// have open files can still be packed.
+#[test]
+fn packs_thousands_of_members() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let members: Vec<(String, Vec<u8>)> = (0..3000)
+        .map(|i| (format!("Data/Entry{}.bin", i), vec![i as u8; 3]))
+        .collect();
+
+    let tar_path = temp_dir.path().join("input.tar");
+    write_tar(&tar_path, &members);
+    let tar_archive = MegaFile::create_from_tar_file(&tar_path, &temp_dir.path().join("tar.meg"),
+                                                     &CreateOptions::default()).unwrap();
+    assert_eq!(tar_archive.get_header().num_files, 3000);
+
+    let zip_path = temp_dir.path().join("input.zip");
+    write_zip(&zip_path, &members);
+    let zip_archive = MegaFile::create_from_zip_file(&zip_path, &temp_dir.path().join("zip.meg"),
+                                                     &CreateOptions::default()).unwrap();
+    assert_eq!(zip_archive.get_header().num_files, 3000);
+}
+
+#[test]
+fn gzipped_tars_match_directory_input() {
+    let (temp_dir, input_dir) = common::create_fixture_directory();
+    let directory_archive = temp_dir.path().join("directory.meg");
+    MegaFile::create_from_directory(&input_dir, &directory_archive).unwrap();
+    let expected = std::fs::read(&directory_archive).unwrap();
+
+    for name in &["input.tar.gz", "input.tgz"] {
+        let tar_gz_path = temp_dir.path().join(name);
+        write_tar_gz(&tar_gz_path, &fixture_members());
+        let input = CreateInput::detect(&tar_gz_path).unwrap();
+        assert!(matches!(input, CreateInput::TarFile(_)));
+
+        let tar_gz_archive = temp_dir.path().join("tar_gz.meg");
+        MegaFile::create_from_input(input, &tar_gz_archive, &CreateOptions::default()).unwrap();
+        assert_eq!(std::fs::read(&tar_gz_archive).unwrap(), expected);
+
+        let stream_archive = temp_dir.path().join("stream.meg");
+        MegaFile::create_from_tar_reader(File::open(&tar_gz_path).unwrap(), &stream_archive,
+                                         &CreateOptions::default()).unwrap();
+        assert_eq!(std::fs::read(&stream_archive).unwrap(), expected);
+    }
+}
+
+#[test]
+fn unsupported_input_files_are_rejected() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let input_path = temp_dir.path().join("input.tar.xz");
+    common::write_file(&input_path, b"not a supported input");
+
+    assert!(matches!(CreateInput::detect(&input_path), Err(MegError::UnsupportedInput(path)) if path == input_path));
+    assert!(matches!(CreateInput::detect(temp_dir.path()), Ok(CreateInput::Directory(_))));
+}
@@ -0,0 +1,102 @@
+#![allow(dead_code)]
+
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use tempfile::TempDir;
+
+use meg_file_creator::{CreateOptions, EncryptionKey, MegaFile, MegVersion};
+use meg_file_creator::crc::crc32;
+
+pub const FIXTURE_FILES: &[(&str, &[u8])] = &[
+    ("Data/XML/Units.xml", b"<Units><Unit Name=\"X-Wing\"/></Units>"),
+    ("Data/XML/GameConstants.xml", b"<GameConstants/>"),
+    ("Data/Art/Textures/empty.dds", b""),
+];
+
+pub fn binary_fixture_content() -> Vec<u8> {
+    (0..70_000u32).map(|i| (i.wrapping_mul(2_654_435_761) >> 13) as u8).collect()
+}
+
+pub fn create_fixture_directory() -> (TempDir, PathBuf) {
+    let temp_dir = tempfile::Builder::new().prefix("meg-test").tempdir().unwrap();
+    let input_dir = temp_dir.path().join("input");
+
+    for (name, content) in FIXTURE_FILES {
+        write_file(&input_dir.join(name), content);
+    }
+    write_file(&input_dir.join("Data/Audio/music.bin"), &binary_fixture_content());
+
+    (temp_dir, input_dir)
+}
+
+// Writes a v1 archive with one record per (crc, name index) pair, in the given order.
+pub fn write_raw_archive(path: &Path, names: &[&str], records: &[(u32, u32)]) {
+    let mut content = Vec::new();
+    content.extend_from_slice(&(names.len() as u32).to_le_bytes());
+    content.extend_from_slice(&(records.len() as u32).to_le_bytes());
+    for name in names {
+        content.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        content.extend_from_slice(name.as_bytes());
+    }
+
+    let data_start = content.len() + records.len() * 20;
+    for (index, (crc, name)) in records.iter().enumerate() {
+        for field in &[*crc, index as u32, 1, (data_start + index) as u32, *name] {
+            content.extend_from_slice(&field.to_le_bytes());
+        }
+    }
+    content.extend((0..records.len()).map(|index| index as u8));
+    std::fs::write(path, content).unwrap();
+}
+
+// Writes a v1 archive with one record per name, sorted by CRC like a well formed archive.
+pub fn write_sorted_raw_archive(path: &Path, names: &[&str]) {
+    let mut records: Vec<(u32, u32)> = names.iter()
+                                            .enumerate()
+                                            .map(|(i, name)| (crc32::compute_from_bytes(name.as_bytes()), i as u32))
+                                            .collect();
+    records.sort();
+    write_raw_archive(path, names, &records);
+}
+
+pub fn write_file(path: &Path, content: &[u8]) {
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(path, content).unwrap();
+}
+
+pub fn test_key() -> EncryptionKey {
+    EncryptionKey::create(*b"0123456789abcdef", *b"fedcba9876543210")
+}
+
+pub fn encrypted_options() -> CreateOptions {
+    CreateOptions { version: MegVersion::V3, encryption: Some(test_key()), ..CreateOptions::default() }
+}
+
+pub fn internal_name(name: &str) -> String {
+    name.replace('/', "\\").to_ascii_uppercase()
+}
+
+// Where extraction puts an entry packed from the given fixture path.
+pub fn extracted_path(output_dir: &Path, name: &str) -> PathBuf {
+    output_dir.join(name.to_ascii_uppercase())
+}
+
+// Reads the content of the entry stored under the given path.
+pub fn read_entry(mega_file: &MegaFile, name: &str) -> Vec<u8> {
+    let meta = mega_file.find(name).unwrap();
+    let mut content = Vec::new();
+    mega_file.open_entry(&meta.table_record).unwrap().read_to_end(&mut content).unwrap();
+    content
+}
+
+// Extracts an archive packed from the fixture directory and checks every file came back.
+pub fn assert_extracts_fixture(mega_file: &MegaFile, output_dir: &Path) {
+    mega_file.extract_files_to(output_dir).unwrap();
+    for (name, content) in FIXTURE_FILES {
+        assert_eq!(fs::read(extracted_path(output_dir, name)).unwrap(), *content);
+    }
+    let extracted = extracted_path(output_dir, "Data/Audio/music.bin");
+    assert_eq!(fs::read(extracted).unwrap(), binary_fixture_content());
+}
@@ -0,0 +1,66 @@
+mod common;
+
+use std::fs;
+
+use meg_file_creator::{CreateOptions, MegError, MegaFile, MegVersion};
+
+#[test]
+fn conversions_keep_names_and_content() {
+    let (temp_dir, input_dir) = common::create_fixture_directory();
+    let original_path = temp_dir.path().join("original.meg");
+    let original = MegaFile::create_from_directory(&input_dir, &original_path).unwrap();
+
+    let v3_path = temp_dir.path().join("v3.meg");
+    let v3_options = CreateOptions { version: MegVersion::V3, encryption: Some(common::test_key()),
+                                     ..CreateOptions::default() };
+    let v3 = original.convert_to(&v3_path, &v3_options).unwrap();
+    assert_eq!(MegaFile::create_with_key(&v3_path, common::test_key()).unwrap().get_version(), MegVersion::V3);
+
+    let v2_path = temp_dir.path().join("v2.meg");
+    let v2 = v3.convert_to(&v2_path, &CreateOptions { version: MegVersion::V2, ..CreateOptions::default() })
+               .unwrap();
+    assert_eq!(MegaFile::create(&v2_path).unwrap().get_version(), MegVersion::V2);
+    assert_eq!(v2.get_file_name_iterator().collect::<Vec<&String>>(),
+               original.get_file_name_iterator().collect::<Vec<&String>>());
+
+    let round_trip_path = temp_dir.path().join("round_trip.meg");
+    v2.convert_to(&round_trip_path, &CreateOptions::default()).unwrap();
+    assert_eq!(fs::read(&round_trip_path).unwrap(), fs::read(&original_path).unwrap());
+}
+
+#[test]
+fn encrypted_archives_convert_to_plain_ones() {
+    let (temp_dir, input_dir) = common::create_fixture_directory();
+    let encrypted_path = temp_dir.path().join("encrypted.meg");
+    let options = CreateOptions { version: MegVersion::V3, encryption: Some(common::test_key()),
+                                  ..CreateOptions::default() };
+    let encrypted = MegaFile::create_from_directory_with_options(&input_dir, &encrypted_path, &options).unwrap();
+
+    let plain_path = temp_dir.path().join("plain.meg");
+    encrypted.convert_to(&plain_path, &CreateOptions { version: MegVersion::V3, ..CreateOptions::default() })
+             .unwrap();
+    let plain = MegaFile::create(&plain_path).unwrap();
+    assert!(!plain.get_header().encrypted);
+
+    let output_dir = temp_dir.path().join("output");
+    plain.extract_files_to(&output_dir).unwrap();
+    let extracted = common::extracted_path(&output_dir, "Data/Audio/music.bin");
+    assert_eq!(fs::read(extracted).unwrap(), common::binary_fixture_content());
+}
+
+#[test]
+fn refuses_to_convert_over_the_input() {
+    let (temp_dir, input_dir) = common::create_fixture_directory();
+    let archive_path = temp_dir.path().join("archive.meg");
+    let mega_file = MegaFile::create_from_directory(&input_dir, &archive_path).unwrap();
+    let content = fs::read(&archive_path).unwrap();
+
+    let link_path = temp_dir.path().join("link.meg");
+    fs::hard_link(&archive_path, &link_path).unwrap();
+    let same_file_paths = [archive_path.clone(), temp_dir.path().join(".").join("archive.meg"), link_path];
+    for path in &same_file_paths {
+        let options = CreateOptions { version: MegVersion::V2, ..CreateOptions::default() };
+        assert!(matches!(mega_file.convert_to(path, &options), Err(MegError::OutputIsInput(_))));
+    }
+    assert_eq!(fs::read(&archive_path).unwrap(), content);
+}
@@ -0,0 +1,11 @@
+use meg_file_creator::crc::crc32;
+
+#[test]
+fn computes_standard_check_value() {
+    assert_eq!(crc32::compute_from_bytes(b"123456789"), 0xCBF43926);
+}
+
+#[test]
+fn empty_input_has_zero_crc() {
+    assert_eq!(crc32::compute_from_bytes(b""), 0);
+}
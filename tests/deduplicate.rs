@@ -0,0 +1,33 @@
+mod common;
+
+use std::fs;
+
+use meg_file_creator::{CreateOptions, MegaFile};
+
+#[test]
+fn identical_content_is_stored_once() {
+    let (temp_dir, input_dir) = common::create_fixture_directory();
+    common::write_file(&input_dir.join("Data/Audio/copy.bin"), &common::binary_fixture_content());
+
+    // Encrypted entries share their padded data just like plain ones.
+    for (i, options) in vec![CreateOptions::default(), common::encrypted_options()].into_iter().enumerate() {
+        let plain_path = temp_dir.path().join(format!("plain{}.meg", i));
+        let deduplicated_path = temp_dir.path().join(format!("deduplicated{}.meg", i));
+        let plain = MegaFile::create_from_directory_with_options(&input_dir, &plain_path, &options).unwrap();
+        let options = CreateOptions { deduplicate: true, ..options };
+        let deduplicated = MegaFile::create_from_directory_with_options(&input_dir, &deduplicated_path, &options)
+            .unwrap();
+
+        assert_eq!(plain.get_shared_data_size(), 0);
+        let shared_size = deduplicated.get_shared_data_size();
+        assert!(shared_size >= 70_000);
+        assert_eq!(fs::metadata(&plain_path).unwrap().len() - fs::metadata(&deduplicated_path).unwrap().len(),
+                   shared_size);
+        assert_eq!(deduplicated.verify().unwrap(), vec![]);
+
+        let output_dir = temp_dir.path().join(format!("output{}", i));
+        common::assert_extracts_fixture(&deduplicated, &output_dir);
+        let copy = common::extracted_path(&output_dir, "Data/Audio/copy.bin");
+        assert_eq!(fs::read(copy).unwrap(), common::binary_fixture_content());
+    }
+}
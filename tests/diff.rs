@@ -0,0 +1,59 @@
+mod common;
+
+use meg_file_creator::{ContentHash, EntryChange, MegaFile};
+
+#[test]
+fn reports_added_removed_and_modified_entries() {
+    let (temp_dir, input_dir) = common::create_fixture_directory();
+    let old = MegaFile::create_from_directory(&input_dir, &temp_dir.path().join("old.meg")).unwrap();
+
+    // Same size, different content, so only the hash tells the change apart.
+    common::write_file(&input_dir.join("Data/XML/GameConstants.xml"), b"<GameConstantz/>");
+    common::write_file(&input_dir.join("Data/XML/Units.xml"), b"<Units/>");
+    common::write_file(&input_dir.join("Data/XML/Hardpoints.xml"), b"<Hardpoints/>");
+    std::fs::remove_file(input_dir.join("Data/Art/Textures/empty.dds")).unwrap();
+    let new = MegaFile::create_from_directory(&input_dir, &temp_dir.path().join("new.meg")).unwrap();
+
+    let changes = old.diff(&new).unwrap();
+    let summary: Vec<(String, &str)> = changes
+        .iter()
+        .map(|change| (change.get_name().to_string(), match change {
+            EntryChange::Added { .. } => "added",
+            EntryChange::Removed { .. } => "removed",
+            EntryChange::Modified { .. } => "modified",
+        }))
+        .collect();
+    assert_eq!(summary, vec![
+        ("DATA\\ART\\TEXTURES\\EMPTY.DDS".to_string(), "removed"),
+        ("DATA\\XML\\GAMECONSTANTS.XML".to_string(), "modified"),
+        ("DATA\\XML\\HARDPOINTS.XML".to_string(), "added"),
+        ("DATA\\XML\\UNITS.XML".to_string(), "modified"),
+    ]);
+
+    match &changes[1] {
+        EntryChange::Modified { old_size, new_size, old_hash, new_hash, .. } => {
+            assert_eq!(old_size, new_size);
+            assert_ne!(old_hash, new_hash);
+        },
+        _ => panic!("expected a modified entry")
+    }
+}
+
+#[test]
+fn identical_archives_have_no_changes() {
+    let (temp_dir, input_dir) = common::create_fixture_directory();
+    let old = MegaFile::create_from_directory(&input_dir, &temp_dir.path().join("old.meg")).unwrap();
+    let new = MegaFile::create_from_directory(&input_dir, &temp_dir.path().join("new.meg")).unwrap();
+
+    assert_eq!(old.diff(&new).unwrap(), vec![]);
+}
+
+#[test]
+fn hashes_entries_as_sha256() {
+    let (temp_dir, input_dir) = common::create_fixture_directory();
+    let mega_file = MegaFile::create_from_directory(&input_dir, &temp_dir.path().join("archive.meg")).unwrap();
+
+    let empty = mega_file.find("Data/Art/Textures/empty.dds").unwrap();
+    let hash: ContentHash = mega_file.hash_entry(&empty.table_record).unwrap();
+    assert_eq!(hash.to_string(), "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855");
+}
@@ -0,0 +1,67 @@
+mod common;
+
+use std::fs;
+
+use meg_file_creator::{CreateOptions, MegaFile, MegError, MegVersion};
+
+#[test]
+fn truncated_header_is_reported() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let archive_path = temp_dir.path().join("short.meg");
+    fs::write(&archive_path, [0x01, 0x00]).unwrap();
+
+    assert!(matches!(MegaFile::create(&archive_path), Err(MegError::TruncatedHeader)));
+}
+
+#[test]
+fn non_utf8_name_is_reported() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let archive_path = temp_dir.path().join("bad_name.meg");
+    let mut content = vec![1, 0, 0, 0, 0, 0, 0, 0];
+    content.extend_from_slice(&[2, 0, 0xC3, 0x28]);
+    fs::write(&archive_path, content).unwrap();
+
+    assert!(matches!(MegaFile::create(&archive_path), Err(MegError::BadNameEncoding { .. })));
+}
+
+#[cfg(unix)]
+#[test]
+fn non_utf8_path_is_reported() {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let input_dir = temp_dir.path().join("input");
+    common::write_file(&input_dir.join(OsStr::from_bytes(&[0xC3, 0x28])), b"content");
+
+    let result = MegaFile::create_from_directory(&input_dir, &temp_dir.path().join("archive.meg"));
+    assert!(matches!(result, Err(MegError::BadNameEncoding { bytes }) if bytes == [0xC3, 0x28]));
+}
+
+#[test]
+fn out_of_bounds_record_is_reported() {
+    let (temp_dir, input_dir) = common::create_fixture_directory();
+    let archive_path = temp_dir.path().join("archive.meg");
+    MegaFile::create_from_directory(&input_dir, &archive_path).unwrap();
+
+    let mut content = fs::read(&archive_path).unwrap();
+    content.truncate(content.len() - 10);
+    fs::write(&archive_path, content).unwrap();
+
+    assert!(matches!(MegaFile::create(&archive_path), Err(MegError::RecordOutOfBounds { .. })));
+}
+
+#[test]
+fn encryption_requires_version_3() {
+    let (temp_dir, input_dir) = common::create_fixture_directory();
+    let options = CreateOptions {
+        version: MegVersion::V2,
+        encryption: Some(common::test_key()),
+        ..CreateOptions::default()
+    };
+
+    let result = MegaFile::create_from_directory_with_options(&input_dir,
+                                                              &temp_dir.path().join("archive.meg"),
+                                                              &options);
+    assert!(matches!(result, Err(MegError::EncryptionNotSupported { version: MegVersion::V2 })));
+}
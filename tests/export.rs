@@ -0,0 +1,58 @@
+mod common;
+
+use std::io::Read;
+
+use meg_file_creator::{CreateOptions, ExtractOptions, MegaFile, MegVersion};
+
+fn read_tar(content: &[u8]) -> Vec<(String, Vec<u8>)> {
+    let mut archive = tar::Archive::new(content);
+    let mut entries: Vec<(String, Vec<u8>)> = archive
+        .entries()
+        .unwrap()
+        .map(|entry| {
+            let mut entry = entry.unwrap();
+            let path = entry.path().unwrap().to_str().unwrap().to_string();
+            let mut data = Vec::new();
+            entry.read_to_end(&mut data).unwrap();
+            (path, data)
+        })
+        .collect();
+    entries.sort();
+    entries
+}
+
+#[test]
+fn exports_entries_with_slash_paths() {
+    let (temp_dir, input_dir) = common::create_fixture_directory();
+    let options = CreateOptions {
+        version: MegVersion::V3,
+        encryption: Some(common::test_key()),
+        ..CreateOptions::default()
+    };
+    let mega_file = MegaFile::create_from_directory_with_options(&input_dir,
+                                                                 &temp_dir.path().join("archive.meg"),
+                                                                 &options).unwrap();
+
+    let tar_content = mega_file.export_tar(Vec::new(), &ExtractOptions::default()).unwrap();
+    let entries = read_tar(&tar_content);
+
+    let mut expected: Vec<(String, Vec<u8>)> = common::FIXTURE_FILES
+        .iter()
+        .map(|(name, content)| (name.to_uppercase(), content.to_vec()))
+        .collect();
+    expected.push(("DATA/AUDIO/MUSIC.BIN".to_string(), common::binary_fixture_content()));
+    expected.sort();
+    assert_eq!(entries, expected);
+}
+
+#[test]
+fn exports_only_matching_entries() {
+    let (temp_dir, input_dir) = common::create_fixture_directory();
+    let mega_file = MegaFile::create_from_directory(&input_dir, &temp_dir.path().join("archive.meg")).unwrap();
+
+    let options = ExtractOptions { patterns: vec!["Data/XML/*".to_string()], ..ExtractOptions::default() };
+    let tar_content = mega_file.export_tar(Vec::new(), &options).unwrap();
+
+    let names: Vec<String> = read_tar(&tar_content).into_iter().map(|(name, _data)| name).collect();
+    assert_eq!(names, vec!["DATA/XML/GAMECONSTANTS.XML", "DATA/XML/UNITS.XML"]);
+}
@@ -0,0 +1,142 @@
+mod common;
+
+use std::path::Path;
+
+use meg_file_creator::{ExtractOptions, MegaFile, MegError, PathPolicy};
+
+fn extracted_names(output_dir: &Path) -> Vec<String> {
+    let mut names = Vec::new();
+    for entry in std::fs::read_dir(output_dir).unwrap() {
+        let path = entry.unwrap().path();
+        let name = path.file_name().unwrap().to_str().unwrap().to_string();
+        if path.is_dir() {
+            names.extend(extracted_names(&path).iter().map(|nested| format!("{}/{}", name, nested)));
+        }
+        else {
+            names.push(name);
+        }
+    }
+    names.sort();
+    names
+}
+
+#[test]
+fn extracts_single_entry_case_insensitively() {
+    let (temp_dir, input_dir) = common::create_fixture_directory();
+    let archive_path = temp_dir.path().join("archive.meg");
+    MegaFile::create_from_directory(&input_dir, &archive_path).unwrap();
+    let mega_file = MegaFile::create(&archive_path).unwrap();
+
+    let units_name = common::internal_name("Data/XML/Units.xml");
+    let output_dir = temp_dir.path().join("output");
+    let options = ExtractOptions {
+        patterns: vec![units_name.to_lowercase().replace('\\', "/")],
+        ..ExtractOptions::default()
+    };
+    mega_file.extract_files_with_options(&output_dir, &options).unwrap();
+
+    assert_eq!(extracted_names(&output_dir), vec!["DATA/XML/UNITS.XML"]);
+}
+
+#[test]
+fn extracts_entries_matching_glob() {
+    let (temp_dir, input_dir) = common::create_fixture_directory();
+    let archive_path = temp_dir.path().join("archive.meg");
+    MegaFile::create_from_directory(&input_dir, &archive_path).unwrap();
+    let mega_file = MegaFile::create(&archive_path).unwrap();
+
+    let output_dir = temp_dir.path().join("output");
+    let options = ExtractOptions {
+        patterns: vec!["DATA\\XML\\*.XML".to_string()],
+        ..ExtractOptions::default()
+    };
+    mega_file.extract_files_with_options(&output_dir, &options).unwrap();
+
+    assert_eq!(extracted_names(&output_dir), vec!["DATA/XML/GAMECONSTANTS.XML", "DATA/XML/UNITS.XML"]);
+}
+
+#[test]
+fn unmatched_pattern_is_an_error() {
+    let (temp_dir, input_dir) = common::create_fixture_directory();
+    let archive_path = temp_dir.path().join("archive.meg");
+    MegaFile::create_from_directory(&input_dir, &archive_path).unwrap();
+    let mega_file = MegaFile::create(&archive_path).unwrap();
+
+    let options = ExtractOptions {
+        patterns: vec!["*.XML".to_string(), "MISSING.TXT".to_string()],
+        ..ExtractOptions::default()
+    };
+    let result = mega_file.extract_files_with_options(&temp_dir.path().join("output"), &options);
+
+    match result {
+        Err(MegError::UnmatchedPatterns(patterns)) => assert_eq!(patterns, vec!["MISSING.TXT"]),
+        _ => panic!("expected unmatched pattern error")
+    }
+}
+
+const UNSAFE_NAMES: &[&str] = &["../ESCAPED.TXT", "/ABSOLUTE.TXT", "C:DRIVE.TXT", "NUL\0.TXT"];
+
+fn create_unsafe_archive(archive_path: &Path) -> MegaFile {
+    let mut names = UNSAFE_NAMES.to_vec();
+    names.push("SAFE.TXT");
+    let records: Vec<(u32, u32)> = (0..names.len() as u32).map(|i| (i, i)).collect();
+    common::write_raw_archive(archive_path, &names, &records);
+    MegaFile::create(archive_path).unwrap()
+}
+
+fn extract_with_policy(mega_file: &MegaFile, output_dir: &Path, path_policy: PathPolicy) -> Result<(), MegError> {
+    let options = ExtractOptions { path_policy, ..ExtractOptions::default() };
+    mega_file.extract_files_with_options(output_dir, &options)
+}
+
+#[test]
+fn rejects_unsafe_entry_names() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let mega_file = create_unsafe_archive(&temp_dir.path().join("archive.meg"));
+    let output_dir = temp_dir.path().join("output").join("nested");
+
+    match extract_with_policy(&mega_file, &output_dir, PathPolicy::Reject) {
+        Err(MegError::UnsafeEntryNames(names)) => assert_eq!(names, UNSAFE_NAMES),
+        _ => panic!("expected unsafe entry name error")
+    }
+    assert!(!output_dir.exists());
+    assert!(!temp_dir.path().join("output").join("ESCAPED.TXT").exists());
+}
+
+#[test]
+fn skips_unsafe_entry_names() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let mega_file = create_unsafe_archive(&temp_dir.path().join("archive.meg"));
+    let output_dir = temp_dir.path().join("output").join("nested");
+
+    extract_with_policy(&mega_file, &output_dir, PathPolicy::Skip).unwrap();
+    assert_eq!(extracted_names(&output_dir), vec!["SAFE.TXT"]);
+    assert!(!temp_dir.path().join("output").join("ESCAPED.TXT").exists());
+}
+
+#[test]
+fn strips_unsafe_components_from_entry_names() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let mega_file = create_unsafe_archive(&temp_dir.path().join("archive.meg"));
+    let output_dir = temp_dir.path().join("output").join("nested");
+
+    extract_with_policy(&mega_file, &output_dir, PathPolicy::Strip).unwrap();
+    assert_eq!(extracted_names(&output_dir),
+               vec!["ABSOLUTE.TXT", "DRIVE.TXT", "ESCAPED.TXT", "NUL.TXT", "SAFE.TXT"]);
+    assert!(!temp_dir.path().join("output").join("ESCAPED.TXT").exists());
+}
+
+#[test]
+fn splits_entry_names_into_directories() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let archive_path = temp_dir.path().join("archive.meg");
+    common::write_raw_archive(&archive_path, &["DATA\\XML\\UNITS.XML", "DATA/ART/MIXED\\SEPARATORS.DDS"],
+                              &[(0, 0), (1, 1)]);
+    let mega_file = MegaFile::create(&archive_path).unwrap();
+
+    let output_dir = temp_dir.path().join("output");
+    mega_file.extract_files_to(&output_dir).unwrap();
+
+    assert!(output_dir.join("DATA").join("XML").join("UNITS.XML").is_file());
+    assert!(output_dir.join("DATA").join("ART").join("MIXED").join("SEPARATORS.DDS").is_file());
+}
@@ -0,0 +1,32 @@
+mod common;
+
+use meg_file_creator::crc::crc32;
+use meg_file_creator::MegaFile;
+
+#[test]
+fn finds_entries_by_normalized_path() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let archive_path = temp_dir.path().join("archive.meg");
+    let names = ["DATA\\XML\\UNITS.XML", "DATA\\ART\\TEXTURES\\EMPTY.DDS", "DATA\\AUDIO\\MUSIC.BIN"];
+    common::write_sorted_raw_archive(&archive_path, &names);
+
+    let mega_file = MegaFile::create(&archive_path).unwrap();
+    let found = mega_file.find("data/xml/Units.xml").unwrap();
+    assert_eq!(found.internal_file_name.filename, "DATA\\XML\\UNITS.XML");
+    assert!(mega_file.find("Data\\Audio\\Music.bin").is_some());
+    assert!(mega_file.find("Data\\XML\\Missing.xml").is_none());
+}
+
+#[test]
+fn resolves_crc_collisions_by_name() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let archive_path = temp_dir.path().join("archive.meg");
+    let crc = crc32::compute_from_bytes(b"DATA\\B.XML");
+    common::write_raw_archive(&archive_path, &["DATA\\A.XML", "DATA\\B.XML"], &[(crc, 0), (crc, 1)]);
+
+    let mega_file = MegaFile::create(&archive_path).unwrap();
+    let found = mega_file.find("Data\\B.xml").unwrap();
+    assert_eq!(found.internal_file_name.filename, "DATA\\B.XML");
+    assert_eq!(found.table_record.index, 1);
+    assert!(mega_file.find("Data\\A.xml").is_none());
+}
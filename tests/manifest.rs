@@ -0,0 +1,73 @@
+mod common;
+
+use meg_file_creator::{CreateOptions, MegaFile, MegError};
+
+#[test]
+fn packs_renamed_files_in_manifest_data_order() {
+    let (temp_dir, input_dir) = common::create_fixture_directory();
+    let manifest_path = input_dir.join("manifest.toml");
+    common::write_file(&manifest_path, br#"
+data_order = "manifest"
+
+[[files]]
+source = "Data/Audio/music.bin"
+name = "Data/Music/Theme.bin"
+
+[[files]]
+source = "Data/XML/Units.xml"
+name = "Data\\Xml\\StarWarsUnits.xml"
+
+[[files]]
+source = "Data/XML/GameConstants.xml"
+"#);
+
+    let archive_path = temp_dir.path().join("archive.meg");
+    let mega_file = MegaFile::create_from_manifest(&manifest_path, &archive_path, &CreateOptions::default()).unwrap();
+
+    assert_eq!(mega_file.get_file_name_iterator().collect::<Vec<&String>>(),
+               ["DATA\\MUSIC\\THEME.BIN", "DATA\\XML\\STARWARSUNITS.XML", "DATA\\XML\\GAMECONSTANTS.XML"]);
+    assert_eq!(common::read_entry(&mega_file, "Data/Music/Theme.bin"), common::binary_fixture_content());
+    assert_eq!(common::read_entry(&mega_file, "Data/XML/StarWarsUnits.xml"), common::FIXTURE_FILES[0].1);
+
+    let starts: Vec<u32> = ["Data/Music/Theme.bin", "Data/XML/StarWarsUnits.xml", "Data/XML/GameConstants.xml"]
+        .iter()
+        .map(|name| mega_file.find(name).unwrap().table_record.start)
+        .collect();
+    assert!(starts.windows(2).all(|pair| pair[0] < pair[1]));
+    assert_eq!(mega_file.verify().unwrap(), vec![]);
+}
+
+#[test]
+fn json_manifest_names_files_after_their_sources() {
+    let (temp_dir, input_dir) = common::create_fixture_directory();
+    let manifest_path = input_dir.join("manifest.json");
+    common::write_file(&manifest_path, br#"{ "files": [ { "source": "Data/XML/Units.xml" } ] }"#);
+
+    let options = CreateOptions { mount_prefix: "Mod".to_string(), ..CreateOptions::default() };
+    let mega_file = MegaFile::create_from_manifest(&manifest_path, &temp_dir.path().join("archive.meg"), &options)
+        .unwrap();
+
+    assert_eq!(mega_file.get_file_name_iterator().collect::<Vec<&String>>(), ["MOD\\DATA\\XML\\UNITS.XML"]);
+}
+
+#[test]
+fn duplicate_names_are_an_error() {
+    let (temp_dir, input_dir) = common::create_fixture_directory();
+    let manifest_path = input_dir.join("manifest.toml");
+    common::write_file(&manifest_path, br#"
+[[files]]
+source = "Data/XML/Units.xml"
+name = "DATA\\UNITS.XML"
+
+[[files]]
+source = "Data/XML/GameConstants.xml"
+name = "data/units.xml"
+"#);
+
+    let result = MegaFile::create_from_manifest(&manifest_path, &temp_dir.path().join("archive.meg"),
+                                                &CreateOptions::default());
+    match result {
+        Err(MegError::DuplicateEntries(names)) => assert_eq!(names, vec!["DATA\\UNITS.XML"]),
+        _ => panic!("expected duplicate entry error")
+    }
+}
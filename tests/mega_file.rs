@@ -0,0 +1,196 @@
+mod common;
+
+use std::fs;
+use std::io::Read;
+
+use meg_file_creator::{CreateOptions, MegaFile, MegVersion, MergeSource, PackEntry, UpdateOptions};
+use meg_file_creator::crc::crc32;
+
+fn create_and_reopen(version: MegVersion) {
+    let (temp_dir, input_dir) = common::create_fixture_directory();
+    let archive_path = temp_dir.path().join("archive.meg");
+    let options = CreateOptions { version, ..CreateOptions::default() };
+
+    MegaFile::create_from_directory_with_options(&input_dir, &archive_path, &options).unwrap();
+    let mega_file = MegaFile::create(&archive_path).unwrap();
+
+    assert_eq!(mega_file.get_version(), version);
+    assert_eq!(mega_file.get_header().num_files, 4);
+    assert_eq!(mega_file.get_file_name_iterator().count(), 4);
+
+    common::assert_extracts_fixture(&mega_file, &temp_dir.path().join("output"));
+}
+
+#[test]
+fn round_trips_version_1() {
+    create_and_reopen(MegVersion::V1);
+}
+
+#[test]
+fn round_trips_version_2() {
+    create_and_reopen(MegVersion::V2);
+}
+
+#[test]
+fn round_trips_version_3() {
+    create_and_reopen(MegVersion::V3);
+}
+
+#[test]
+fn records_are_sorted_by_crc() {
+    let (temp_dir, input_dir) = common::create_fixture_directory();
+    let archive_path = temp_dir.path().join("archive.meg");
+    MegaFile::create_from_directory(&input_dir, &archive_path).unwrap();
+
+    let mega_file = MegaFile::create(&archive_path).unwrap();
+    let crcs: Vec<u32> = mega_file.get_metadata_iterator()
+                                  .map(|meta| meta.table_record.crc)
+                                  .collect();
+    let mut sorted_crcs = crcs.clone();
+    sorted_crcs.sort();
+    assert_eq!(crcs, sorted_crcs);
+
+    for meta in mega_file.get_metadata_iterator() {
+        let name_crc = meg_file_creator::crc::crc32::compute_from_bytes(meta.internal_file_name.filename.as_bytes());
+        assert_eq!(meta.table_record.crc, name_crc);
+    }
+}
+
+#[test]
+fn encrypted_archive_requires_key() {
+    let (temp_dir, input_dir) = common::create_fixture_directory();
+    let archive_path = temp_dir.path().join("encrypted.meg");
+    let options = CreateOptions {
+        version: MegVersion::V3,
+        encryption: Some(common::test_key()),
+        ..CreateOptions::default()
+    };
+    MegaFile::create_from_directory_with_options(&input_dir, &archive_path, &options).unwrap();
+
+    assert!(MegaFile::create(&archive_path).is_err());
+
+    let mega_file = MegaFile::create_with_key(&archive_path, common::test_key()).unwrap();
+    assert_eq!(mega_file.get_version(), MegVersion::V3);
+    assert!(mega_file.get_header().encrypted);
+    assert!(mega_file.get_metadata_iterator().all(|meta| meta.table_record.is_encrypted()));
+
+    let output_dir = temp_dir.path().join("output");
+    mega_file.extract_files_to(&output_dir).unwrap();
+    let extracted = common::extracted_path(&output_dir, "Data/Audio/music.bin");
+    assert_eq!(fs::read(extracted).unwrap(), common::binary_fixture_content());
+}
+
+#[test]
+fn converts_between_versions() {
+    let (temp_dir, input_dir) = common::create_fixture_directory();
+    let v1_path = temp_dir.path().join("v1.meg");
+    let v2_path = temp_dir.path().join("v2.meg");
+    let converted_path = temp_dir.path().join("converted.meg");
+
+    MegaFile::create_from_directory(&input_dir, &v1_path).unwrap();
+    MegaFile::create_from_directory_with_options(&input_dir, &v2_path,
+                                                 &CreateOptions { version: MegVersion::V2,
+                                                                  ..CreateOptions::default() })
+        .unwrap();
+
+    let v1_file = MegaFile::create(&v1_path).unwrap();
+    v1_file.convert_to(&converted_path,
+                       &CreateOptions { version: MegVersion::V2, ..CreateOptions::default() })
+           .unwrap();
+
+    assert_eq!(fs::read(&converted_path).unwrap(), fs::read(&v2_path).unwrap());
+    assert_eq!(MegaFile::create(&converted_path).unwrap().get_version(), MegVersion::V2);
+}
+
+#[test]
+fn open_entry_streams_content_in_small_reads() {
+    let (temp_dir, input_dir) = common::create_fixture_directory();
+    let archive_path = temp_dir.path().join("encrypted.meg");
+    let options = CreateOptions {
+        version: MegVersion::V3,
+        encryption: Some(common::test_key()),
+        ..CreateOptions::default()
+    };
+    MegaFile::create_from_directory_with_options(&input_dir, &archive_path, &options).unwrap();
+
+    let mega_file = MegaFile::create_with_key(&archive_path, common::test_key()).unwrap();
+    let music_name = common::internal_name("Data/Audio/music.bin");
+    let music = mega_file.get_metadata_iterator()
+                         .find(|meta| meta.internal_file_name.filename == music_name)
+                         .unwrap();
+
+    let mut reader = mega_file.open_entry(&music.table_record).unwrap();
+    let mut content = Vec::new();
+    let mut chunk = [0; 1000];
+    loop {
+        let read = reader.read(&mut chunk).unwrap();
+        if read == 0 {
+            break;
+        }
+        content.extend_from_slice(&chunk[..read]);
+    }
+    assert_eq!(content, common::binary_fixture_content());
+}
+
+#[test]
+fn names_are_relative_to_the_input_directory() {
+    let (temp_dir, input_dir) = common::create_fixture_directory();
+    let archive_path = temp_dir.path().join("archive.meg");
+    let mega_file = MegaFile::create_from_directory(&input_dir.join("Data"), &archive_path).unwrap();
+
+    let mut names: Vec<&String> = mega_file.get_file_name_iterator().collect();
+    names.sort();
+    assert_eq!(names, ["ART\\TEXTURES\\EMPTY.DDS", "AUDIO\\MUSIC.BIN", "XML\\GAMECONSTANTS.XML", "XML\\UNITS.XML"]);
+}
+
+#[test]
+fn only_ascii_letters_are_upper_cased() {
+    let pack_entry = PackEntry::from_bytes("Data/Straße/ÿ.xml".to_string(), Vec::new()).unwrap();
+
+    assert_eq!(pack_entry.internal_file_name, "DATA\\STRAßE\\ÿ.XML");
+    assert_eq!(pack_entry.table_record.crc, crc32::compute_from_bytes("DATA\\STRAßE\\ÿ.XML".as_bytes()));
+}
+
+#[test]
+fn mount_prefix_is_prepended_and_hashed() {
+    let (temp_dir, input_dir) = common::create_fixture_directory();
+    let archive_path = temp_dir.path().join("archive.meg");
+    let options = CreateOptions { mount_prefix: "data/".to_string(), ..CreateOptions::default() };
+    MegaFile::create_from_directory_with_options(&input_dir.join("Data"), &archive_path, &options).unwrap();
+
+    let mega_file = MegaFile::create(&archive_path).unwrap();
+    for meta in mega_file.get_metadata_iterator() {
+        assert!(meta.internal_file_name.filename.starts_with("DATA\\"));
+        assert_eq!(meta.table_record.crc, crc32::compute_from_bytes(meta.internal_file_name.filename.as_bytes()));
+    }
+    assert!(mega_file.find("Data/XML/Units.xml").is_some());
+}
+
+// Entries of an archive share one handle, so archives with more entries than a process may
+// have open files can still be converted, updated and merged.
+#[test]
+fn rewrites_archives_with_thousands_of_entries() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let entries: Vec<PackEntry> = (0..3000)
+        .map(|i| PackEntry::from_bytes(format!("DATA\\ENTRY{}.BIN", i), vec![i as u8; 3]).unwrap())
+        .collect();
+    let archive_path = temp_dir.path().join("archive.meg");
+    let mega_file = MegaFile::create_from_entries(entries, &archive_path, &CreateOptions::default()).unwrap();
+
+    let converted_path = temp_dir.path().join("converted.meg");
+    let converted = mega_file.convert_to(&converted_path,
+                                         &CreateOptions { version: MegVersion::V2, ..CreateOptions::default() })
+                             .unwrap();
+    assert_eq!(converted.get_header().num_files, 3000);
+
+    let update = UpdateOptions { files: Vec::new(), removals: vec!["DATA\\ENTRY0.BIN".to_string()] };
+    let updated_path = temp_dir.path().join("updated.meg");
+    let updated = mega_file.update_to(&updated_path, &update, &CreateOptions::default()).unwrap();
+    assert_eq!(updated.get_header().num_files, 2999);
+
+    let merged_path = temp_dir.path().join("merged.meg");
+    let sources = [MergeSource::Archive(&mega_file), MergeSource::Archive(&converted)];
+    let (merged, overrides) = MegaFile::merge(&sources, &merged_path, &CreateOptions::default()).unwrap();
+    assert_eq!(merged.get_header().num_files, 3000);
+    assert_eq!(overrides.len(), 3000);
+}
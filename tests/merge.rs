@@ -0,0 +1,45 @@
+mod common;
+
+use meg_file_creator::{CreateOptions, MegaFile, MegError, MergeSource, Override};
+
+#[test]
+fn later_sources_override_earlier_ones() {
+    let (temp_dir, input_dir) = common::create_fixture_directory();
+    let base = MegaFile::create_from_directory(&input_dir, &temp_dir.path().join("base.meg")).unwrap();
+
+    let patch_dir = temp_dir.path().join("patch");
+    common::write_file(&patch_dir.join("Data/XML/Units.xml"), b"<Units Patched=\"1\"/>");
+    common::write_file(&patch_dir.join("Data/XML/Patch.xml"), b"<Patch/>");
+    let patch = MegaFile::create_from_directory(&patch_dir, &temp_dir.path().join("patch.meg")).unwrap();
+
+    let mod_dir = temp_dir.path().join("mod");
+    common::write_file(&mod_dir.join("data/xml/units.xml"), b"<Units Modded=\"1\"/>");
+
+    let sources = [MergeSource::Archive(&base), MergeSource::Archive(&patch), MergeSource::Directory(&mod_dir)];
+    let merged_path = temp_dir.path().join("merged.meg");
+    let (merged, overrides) = MegaFile::merge(&sources, &merged_path, &CreateOptions::default()).unwrap();
+
+    assert_eq!(overrides, vec![
+        Override { name: "DATA\\XML\\UNITS.XML".to_string(), overridden: 0, winner: 1 },
+        Override { name: "DATA\\XML\\UNITS.XML".to_string(), overridden: 1, winner: 2 },
+    ]);
+    assert_eq!(merged.get_header().num_files, 5);
+    assert_eq!(common::read_entry(&merged, "Data/XML/Units.xml"), b"<Units Modded=\"1\"/>");
+    assert_eq!(common::read_entry(&merged, "Data/XML/Patch.xml"), b"<Patch/>");
+    assert_eq!(common::read_entry(&merged, "Data/Audio/music.bin"), common::binary_fixture_content());
+    assert_eq!(merged.verify().unwrap(), vec![]);
+}
+
+#[test]
+fn refuses_to_write_over_a_source_archive() {
+    let (temp_dir, input_dir) = common::create_fixture_directory();
+    let base_path = temp_dir.path().join("base.meg");
+    let base = MegaFile::create_from_directory(&input_dir, &base_path).unwrap();
+    let original = std::fs::read(&base_path).unwrap();
+
+    let sources = [MergeSource::Archive(&base), MergeSource::Directory(&input_dir)];
+    let result = MegaFile::merge(&sources, &base_path, &CreateOptions::default());
+
+    assert!(matches!(result, Err(MegError::OutputIsInput(path)) if path == base_path));
+    assert_eq!(std::fs::read(&base_path).unwrap(), original);
+}
@@ -0,0 +1,92 @@
+mod common;
+
+use std::fs;
+
+use meg_file_creator::{ContentHash, CreateInput, CreateOptions, MegaFile, MegVersion, PackEntry};
+use meg_file_creator::crc::crc32;
+
+// Two names that hash to the same crc.
+const COLLIDING_NAMES: [&str; 2] = ["DATA\\XML\\F8816.XML", "DATA\\XML\\F18632000.XML"];
+
+#[test]
+fn check_reproducible_matches_the_written_archive() {
+    let (temp_dir, input_dir) = common::create_fixture_directory();
+    let archive_path = temp_dir.path().join("archive.meg");
+    let options = CreateOptions { version: MegVersion::V3, encryption: Some(common::test_key()),
+                                  ..CreateOptions::default() };
+
+    let hash = MegaFile::check_reproducible(CreateInput::Directory(&input_dir), &options).unwrap();
+    MegaFile::create_from_input(CreateInput::Directory(&input_dir), &archive_path, &options).unwrap();
+
+    let mut archive = fs::File::open(&archive_path).unwrap();
+    let size = archive.metadata().unwrap().len();
+    assert_eq!(ContentHash::from_reader(&mut archive, size).unwrap(), hash);
+}
+
+#[test]
+fn directories_written_in_different_orders_give_identical_archives() {
+    let (temp_dir, input_dir) = common::create_fixture_directory();
+    let reversed_dir = temp_dir.path().join("reversed");
+    for (name, content) in common::FIXTURE_FILES.iter().rev() {
+        common::write_file(&reversed_dir.join(name), content);
+    }
+    common::write_file(&reversed_dir.join("Data/Audio/music.bin"), &common::binary_fixture_content());
+
+    let archive_path = temp_dir.path().join("archive.meg");
+    let reversed_path = temp_dir.path().join("reversed.meg");
+    MegaFile::create_from_directory(&input_dir, &archive_path).unwrap();
+    MegaFile::create_from_directory(&reversed_dir, &reversed_path).unwrap();
+
+    assert_eq!(fs::read(&archive_path).unwrap(), fs::read(&reversed_path).unwrap());
+}
+
+#[test]
+fn equal_crcs_are_ordered_by_name() {
+    assert_eq!(crc32::compute_from_bytes(COLLIDING_NAMES[0].as_bytes()),
+               crc32::compute_from_bytes(COLLIDING_NAMES[1].as_bytes()));
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let entries = |names: &[&str]| -> Vec<PackEntry> {
+        names.iter()
+             .map(|name| PackEntry::from_bytes(name.to_string(), name.as_bytes().to_vec()).unwrap())
+             .collect()
+    };
+    let forward_path = temp_dir.path().join("forward.meg");
+    let backward_path = temp_dir.path().join("backward.meg");
+    let options = CreateOptions { version: MegVersion::V2, ..CreateOptions::default() };
+    MegaFile::create_from_entries(entries(&COLLIDING_NAMES), &forward_path, &options).unwrap();
+    let backward_names = [COLLIDING_NAMES[1], COLLIDING_NAMES[0]];
+    MegaFile::create_from_entries(entries(&backward_names), &backward_path, &options).unwrap();
+
+    let forward = MegaFile::create(&forward_path).unwrap();
+    let record_names: Vec<String> = forward.get_metadata_iterator()
+                                           .map(|meta| meta.internal_file_name.filename)
+                                           .collect();
+    assert_eq!(record_names, ["DATA\\XML\\F18632000.XML", "DATA\\XML\\F8816.XML"]);
+
+    let backward = MegaFile::create(&backward_path).unwrap();
+    let backward_record_names: Vec<String> = backward.get_metadata_iterator()
+                                                     .map(|meta| meta.internal_file_name.filename)
+                                                     .collect();
+    assert_eq!(backward_record_names, record_names);
+}
+
+#[test]
+fn tar_files_build_reproducibly_like_the_directory() {
+    let (temp_dir, input_dir) = common::create_fixture_directory();
+    let tar_path = temp_dir.path().join("input.tar");
+    let mut builder = tar::Builder::new(fs::File::create(&tar_path).unwrap());
+    builder.append_dir_all(".", &input_dir).unwrap();
+    builder.finish().unwrap();
+
+    let hash = MegaFile::check_reproducible(CreateInput::TarFile(&tar_path), &CreateOptions::default()).unwrap();
+    let archive_path = temp_dir.path().join("archive.meg");
+    MegaFile::create_from_input(CreateInput::TarFile(&tar_path), &archive_path, &CreateOptions::default()).unwrap();
+    let directory_path = temp_dir.path().join("directory.meg");
+    MegaFile::create_from_directory(&input_dir, &directory_path).unwrap();
+
+    assert_eq!(fs::read(&archive_path).unwrap(), fs::read(&directory_path).unwrap());
+    let mut archive = fs::File::open(&archive_path).unwrap();
+    let size = archive.metadata().unwrap().len();
+    assert_eq!(ContentHash::from_reader(&mut archive, size).unwrap(), hash);
+}
@@ -0,0 +1,34 @@
+mod common;
+
+use meg_file_creator::{CreateOptions, MegaFile, MegVersion};
+
+#[test]
+fn serializes_header_and_metadata() {
+    let (temp_dir, input_dir) = common::create_fixture_directory();
+    let options = CreateOptions { version: MegVersion::V2, ..CreateOptions::default() };
+    let mega_file = MegaFile::create_from_directory_with_options(&input_dir,
+                                                                 &temp_dir.path().join("archive.meg"),
+                                                                 &options).unwrap();
+
+    let header = serde_json::to_value(mega_file.get_header()).unwrap();
+    assert_eq!(header["version"], "v2");
+    assert_eq!(header["num_files"], 4);
+    assert_eq!(header["data_start"], mega_file.get_data_start());
+
+    let meta = mega_file.find("Data/XML/Units.xml").unwrap();
+    let value = serde_json::to_value(&meta).unwrap();
+    assert_eq!(value["name"], "DATA\\XML\\UNITS.XML");
+    assert_eq!(value["crc"], format!("{:#010X}", meta.table_record.crc));
+    assert_eq!(value["size"], meta.table_record.size);
+    assert_eq!(value["start"], meta.table_record.start);
+    assert_eq!(value["name_index"], meta.table_record.name);
+}
+
+#[test]
+fn computes_data_start_of_version_1_archives() {
+    let (temp_dir, input_dir) = common::create_fixture_directory();
+    let mega_file = MegaFile::create_from_directory(&input_dir, &temp_dir.path().join("archive.meg")).unwrap();
+
+    let first_start = mega_file.get_metadata_iterator().map(|meta| meta.table_record.start).min().unwrap();
+    assert_eq!(mega_file.get_data_start(), first_start as u64);
+}
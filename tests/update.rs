@@ -0,0 +1,58 @@
+mod common;
+
+use meg_file_creator::{CreateOptions, MegaFile, MegError, UpdateOptions};
+
+#[test]
+fn adds_replaces_and_removes_entries() {
+    let (temp_dir, input_dir) = common::create_fixture_directory();
+    let archive_path = temp_dir.path().join("archive.meg");
+    let mega_file = MegaFile::create_from_directory(&input_dir, &archive_path).unwrap();
+
+    let hotfix_dir = temp_dir.path().join("hotfix");
+    common::write_file(&hotfix_dir.join("Units.xml"), b"<Units/>");
+    common::write_file(&hotfix_dir.join("Hardpoints.xml"), b"<Hardpoints/>");
+    let update = UpdateOptions {
+        files: vec![("Data/XML/Units.xml".to_string(), hotfix_dir.join("Units.xml")),
+                    ("Data/XML/Hardpoints.xml".to_string(), hotfix_dir.join("Hardpoints.xml"))],
+        removals: vec!["data/art/textures/empty.dds".to_string()]
+    };
+    let updated_path = temp_dir.path().join("updated.meg");
+    mega_file.update_to(&updated_path, &update, &CreateOptions::default()).unwrap();
+
+    let updated = MegaFile::create(&updated_path).unwrap();
+    let mut names: Vec<&String> = updated.get_file_name_iterator().collect();
+    names.sort();
+    assert_eq!(names, ["DATA\\AUDIO\\MUSIC.BIN", "DATA\\XML\\GAMECONSTANTS.XML", "DATA\\XML\\HARDPOINTS.XML",
+                       "DATA\\XML\\UNITS.XML"]);
+    assert_eq!(common::read_entry(&updated, "Data/XML/Units.xml"), b"<Units/>");
+    assert_eq!(common::read_entry(&updated, "Data/XML/Hardpoints.xml"), b"<Hardpoints/>");
+    assert_eq!(common::read_entry(&updated, "Data/Audio/music.bin"), common::binary_fixture_content());
+    assert_eq!(updated.verify().unwrap(), vec![]);
+}
+
+#[test]
+fn removing_a_missing_entry_is_an_error() {
+    let (temp_dir, input_dir) = common::create_fixture_directory();
+    let mega_file = MegaFile::create_from_directory(&input_dir, &temp_dir.path().join("archive.meg")).unwrap();
+
+    let update = UpdateOptions { removals: vec!["DATA\\MISSING.TXT".to_string()], ..UpdateOptions::default() };
+    let result = mega_file.update_to(&temp_dir.path().join("updated.meg"), &update, &CreateOptions::default());
+
+    match result {
+        Err(MegError::MissingEntries(names)) => assert_eq!(names, vec!["DATA\\MISSING.TXT"]),
+        _ => panic!("expected missing entry error")
+    }
+}
+
+#[test]
+fn refuses_to_update_over_the_input() {
+    let (temp_dir, input_dir) = common::create_fixture_directory();
+    let archive_path = temp_dir.path().join("archive.meg");
+    let mega_file = MegaFile::create_from_directory(&input_dir, &archive_path).unwrap();
+    let content = std::fs::read(&archive_path).unwrap();
+
+    let update = UpdateOptions { removals: vec!["Data/XML/Units.xml".to_string()], ..UpdateOptions::default() };
+    let result = mega_file.update_to(&archive_path, &update, &CreateOptions::default());
+    assert!(matches!(result, Err(MegError::OutputIsInput(_))));
+    assert_eq!(std::fs::read(&archive_path).unwrap(), content);
+}
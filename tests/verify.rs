@@ -0,0 +1,92 @@
+mod common;
+
+use std::path::Path;
+
+use meg_file_creator::crc::crc32;
+use meg_file_creator::{CreateOptions, Finding, MegaFile, MegVersion};
+
+// Overwrites one u32 field of a record written by common::write_raw_archive.
+fn patch_record(path: &Path, names: &[&str], record: usize, field: usize, value: u32) {
+    let mut content = std::fs::read(path).unwrap();
+    let records_start = 8 + names.iter().map(|name| 2 + name.len()).sum::<usize>();
+    let offset = records_start + record * 20 + field * 4;
+    content[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+    std::fs::write(path, content).unwrap();
+}
+
+#[test]
+fn created_archives_have_no_findings() {
+    let (temp_dir, input_dir) = common::create_fixture_directory();
+    for version in &[MegVersion::V1, MegVersion::V2, MegVersion::V3] {
+        let archive_path = temp_dir.path().join(format!("{}.meg", version));
+        let options = CreateOptions { version: *version, ..CreateOptions::default() };
+        let mega_file = MegaFile::create_from_directory_with_options(&input_dir, &archive_path, &options).unwrap();
+        assert_eq!(mega_file.verify().unwrap(), vec![]);
+    }
+
+    let archive_path = temp_dir.path().join("encrypted.meg");
+    let options = CreateOptions {
+        version: MegVersion::V3,
+        encryption: Some(common::test_key()),
+        ..CreateOptions::default()
+    };
+    MegaFile::create_from_directory_with_options(&input_dir, &archive_path, &options).unwrap();
+    let findings = MegaFile::verify_archive(&archive_path, None, Some(common::test_key())).unwrap();
+    assert_eq!(findings, vec![]);
+}
+
+#[test]
+fn reports_crc_order_and_crc_mismatches() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let archive_path = temp_dir.path().join("archive.meg");
+    let names = ["A.XML", "B.XML"];
+    let crc_b = crc32::compute_from_bytes(b"B.XML");
+    common::write_raw_archive(&archive_path, &names, &[(crc_b, 1), (0, 0)]);
+
+    let findings = MegaFile::verify_archive(&archive_path, None, None).unwrap();
+    assert_eq!(findings, vec![
+        Finding::CrcMismatch { record: 1, stored: 0, computed: crc32::compute_from_bytes(b"A.XML") },
+        Finding::NotSortedByCrc { record: 1, crc: 0, previous_crc: crc_b },
+    ]);
+}
+
+#[test]
+fn reports_broken_records_that_fail_to_open() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let archive_path = temp_dir.path().join("archive.meg");
+    let names = ["A.XML", "B.XML", "C.XML"];
+    common::write_sorted_raw_archive(&archive_path, &names);
+    let file_size = std::fs::metadata(&archive_path).unwrap().len();
+    let data_start = file_size as u32 - 3;
+
+    patch_record(&archive_path, &names, 0, 4, 7);
+    patch_record(&archive_path, &names, 1, 1, 5);
+    patch_record(&archive_path, &names, 0, 2, 2);
+    patch_record(&archive_path, &names, 2, 3, data_start + 3);
+    assert!(MegaFile::create(&archive_path).is_err());
+
+    let findings = MegaFile::verify_archive(&archive_path, None, None).unwrap();
+    assert_eq!(findings, vec![
+        Finding::NameIndexOutOfBounds { record: 0, name: 7 },
+        Finding::IndexMismatch { record: 1, index: 5 },
+        Finding::RecordOutOfBounds { record: 2, start: data_start + 3, size: 1, file_size },
+        Finding::OverlappingData { first: 0, second: 1 },
+    ]);
+}
+
+#[test]
+fn shared_data_is_not_an_overlap() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let archive_path = temp_dir.path().join("archive.meg");
+    let names = ["A.XML", "B.XML", "C.XML"];
+    common::write_sorted_raw_archive(&archive_path, &names);
+    let data_start = std::fs::metadata(&archive_path).unwrap().len() as u32 - 3;
+
+    patch_record(&archive_path, &names, 1, 3, data_start);
+    assert_eq!(MegaFile::verify_archive(&archive_path, None, None).unwrap(), vec![]);
+
+    patch_record(&archive_path, &names, 2, 3, data_start);
+    patch_record(&archive_path, &names, 2, 2, 2);
+    let findings = MegaFile::verify_archive(&archive_path, None, None).unwrap();
+    assert_eq!(findings, vec![Finding::OverlappingData { first: 0, second: 2 }]);
+}
@@ -0,0 +1,89 @@
+mod common;
+
+use std::io::Read;
+use std::path::PathBuf;
+
+use meg_file_creator::{CreateOptions, MegaFile, Vfs, VfsSource};
+
+const MEGA_FILES_XML: &str = "\u{feff}<?xml version=\"1.0\"?>
+<Mega_Files>
+    <!-- <File>Data\\Removed.meg</File> -->
+    <File>Data\\Base.meg</File>
+    <File> DATA\\PATCH.MEG </File>
+</Mega_Files>
+";
+
+// Lays out a game directory with a base archive, a patch archive overriding one entry and
+// a loose file overriding another.
+fn create_game_directory() -> (tempfile::TempDir, PathBuf) {
+    let (temp_dir, input_dir) = common::create_fixture_directory();
+    let game_dir = temp_dir.path().join("game");
+    let options = CreateOptions { mount_prefix: "Data".to_string(), ..CreateOptions::default() };
+    std::fs::create_dir_all(game_dir.join("Data")).unwrap();
+    MegaFile::create_from_directory_with_options(&input_dir.join("Data"), &game_dir.join("Data/Base.meg"), &options)
+        .unwrap();
+
+    let patch_dir = temp_dir.path().join("patch");
+    common::write_file(&patch_dir.join("XML/Units.xml"), b"<Units Patched=\"1\"/>");
+    MegaFile::create_from_directory_with_options(&patch_dir, &game_dir.join("Data/Patch.meg"), &options).unwrap();
+
+    common::write_file(&game_dir.join("Data/XML/GameConstants.xml"), b"<GameConstants Loose=\"1\"/>");
+    common::write_file(&game_dir.join("Data/MegaFiles.xml"), MEGA_FILES_XML.as_bytes());
+    (temp_dir, game_dir)
+}
+
+fn read_all(vfs: &Vfs, path: &str) -> Vec<u8> {
+    let mut content = Vec::new();
+    vfs.open(path).unwrap().read_to_end(&mut content).unwrap();
+    content
+}
+
+#[test]
+fn resolves_paths_by_archive_order_and_loose_files() {
+    let (_temp_dir, game_dir) = create_game_directory();
+    let vfs = Vfs::create(&game_dir.join("Data/MegaFiles.xml"), &game_dir, Some(game_dir.clone()), None).unwrap();
+
+    match vfs.resolve("data/xml/units.xml") {
+        Some(VfsSource::Archive { path, .. }) => assert_eq!(path, game_dir.join("Data/Patch.meg")),
+        _ => panic!("expected the patch archive to win")
+    }
+    assert_eq!(vfs.resolve_all("Data\\XML\\Units.xml").len(), 2);
+    match vfs.resolve("Data\\XML\\GameConstants.XML") {
+        Some(VfsSource::LooseFile(path)) => assert_eq!(path, game_dir.join("Data/XML/GameConstants.xml")),
+        _ => panic!("expected the loose file to win")
+    }
+
+    assert_eq!(read_all(&vfs, "Data\\XML\\Units.xml"), b"<Units Patched=\"1\"/>");
+    assert_eq!(read_all(&vfs, "Data\\XML\\GameConstants.xml"), b"<GameConstants Loose=\"1\"/>");
+    assert_eq!(read_all(&vfs, "Data\\Audio\\Music.bin"), common::binary_fixture_content());
+    assert!(vfs.exists("DATA/ART/TEXTURES/EMPTY.DDS"));
+    assert!(!vfs.exists("Data\\XML\\Missing.xml"));
+    assert!(vfs.open("Data\\XML\\Missing.xml").is_err());
+}
+
+#[test]
+fn lists_names_from_every_source() {
+    let (_temp_dir, game_dir) = create_game_directory();
+    let vfs = Vfs::create(&game_dir.join("Data/MegaFiles.xml"), &game_dir, Some(game_dir.clone()), None).unwrap();
+
+    assert_eq!(vfs.list("data/xml/").unwrap(), vec!["DATA\\XML\\GAMECONSTANTS.XML", "DATA\\XML\\UNITS.XML"]);
+}
+
+#[test]
+fn missing_listed_archive_is_an_error() {
+    let (_temp_dir, game_dir) = create_game_directory();
+    std::fs::remove_file(game_dir.join("Data/Patch.meg")).unwrap();
+
+    assert!(Vfs::create(&game_dir.join("Data/MegaFiles.xml"), &game_dir, None, None).is_err());
+}
+
+#[test]
+fn missing_loose_directory_adds_no_names() {
+    let (_temp_dir, game_dir) = create_game_directory();
+    let loose_directory = game_dir.join("Loose");
+    let vfs = Vfs::create(&game_dir.join("Data/MegaFiles.xml"), &game_dir, Some(loose_directory), None).unwrap();
+
+    let names = vfs.list("").unwrap();
+    assert_eq!(names.len(), 4);
+    assert!(!names.contains(&String::new()));
+}